@@ -48,85 +48,16 @@ const TRI_LUT: [&'static [Side]; 18] = [
     &[Bottom, BotRight, Right, TopLeft, Left, Top], // 17 Disambiguity for case 10
 ];
 
-// fn norm(x: f32) -> f32 {
-//     (x + 2.0) / (4.0)
-// }
-
+/// The point between `a` and `b` where the density crosses `thresh`,
+/// given the densities `w1` at `a` and `w2` at `b`.
 fn lerp(a: Vec2, b: Vec2, w1: f32, w2: f32, thresh: f32) -> Vec2 {
-    let min = w1.min(w2);
-    let max = w1.max(w2);
-
-    // let t = min / (min - max);
-
-    // if w1 > w2 {
-    //     a.lerp(b, t)
-    // } else {
-    //     b.lerp(a, t)
-    // }
-
-    // let (p, p_w, n, n_w) = if w1 > w2 {
-    //     (a, w1, b, w2)
-    // } else {
-    //     (b, w2, a, w1)
-    // };
-
-    // let t = n_w / (n_w - p_w);
-
-    // let x = n.x() + t * (p.x() - n.x());
-    // let y = n.y() + t * (p.x() - n.y());
-
-    // Vec2::new(x, y)
-
-    // let t = w1.min(w2) / (w1.min(w2) - w1.max(w2));
-
-    // if w1 > w2 {
-    //     a.lerp(b, t)
-    // } else {
-    //     b.lerp(a, t)
-    // }
-
-    // let (b, d, b_w, d_w) = if w1 > w2 {
-    //     (b, a, w2, w1)
-    // } else {
-    //     (a, b, w1, w2)
-    // };
-
-    // let v = b + (b - d) * ((thresh - b_w) / (d_w - b_w));
-    // v
-    // d.lerp(b, v / 8.0)
-
-    // let w1 = if w1 < thresh && thresh - w1 < 0.03 {
-    //     w1 + 0.03
-    // } else {
-    //     w1
-    // };
-
-    // let w2 = if w2 < thresh && thresh - w2 < 0.03 {
-    //     w2 + 0.03
-    // } else {
-    //     w2
-    // };
-
-    // let n = (1.0 / (w1)) + (1.0 / (w2));
-    // let d = (w1).recip().abs() + (w2).recip().abs();
-
-    // if w1 > w2 {
-    // println!("b");
-    // b + (n / d)
-    // } else {
-    // println!("a");
-    // a + (n / d)
-    // }
-
-    // let v = (n / d).abs();
+    if (w2 - w1).abs() <= f32::EPSILON {
+        return (a + b) / 2.0;
+    }
 
-    // if w1 > w2 {
-    //     a.lerp(b, v)
-    // } else {
-    //     b.lerp(a, v)
-    // }
+    let t = ((thresh - w1) / (w2 - w1)).clamp(0.0, 1.0);
 
-    (a + b) / 2.0
+    a + (b - a) * t
 }
 
 fn get_point(pts: &[f32; 4], side: Side, cz: CellSize, threshold: f32) -> Vec2 {
@@ -138,36 +69,24 @@ fn get_point(pts: &[f32; 4], side: Side, cz: CellSize, threshold: f32) -> Vec2 {
             let b = Vec2::new(cx, 0.0);
 
             lerp(a, b, pts[0], pts[1], threshold)
-            // a.lerp(b, t)
-            // let diff = norm(pts[1] - pts[0]);
-            // a.lerp(b, diff)
         }
         Right => {
             let a = Vec2::new(cx, 0.0);
             let b = Vec2::new(cx, -cy);
 
             lerp(a, b, pts[1], pts[2], threshold)
-            // a.lerp(b, t)
-            // let diff = norm(pts[2] - pts[1]);
-            // a.lerp(b, diff)
         }
         Bottom => {
             let a = Vec2::new(cx, -cy);
             let b = Vec2::new(0.0, -cy);
 
             lerp(a, b, pts[2], pts[3], threshold)
-            // a.lerp(b, t)
-            // let diff = norm(pts[3] - pts[2]);
-            // a.lerp(b, diff)
         }
         Left => {
             let a = Vec2::new(0.0, -cy);
             let b = Vec2::new(0.0, 0.0);
 
             lerp(a, b, pts[3], pts[0], threshold)
-            // a.lerp(b, t)
-            // let diff = norm(pts[0] - pts[3]);
-            // a.lerp(b, diff)
         }
         TopLeft => Vec2::new(0.0, 0.0),
         TopRight => Vec2::new(cx, 0.0),
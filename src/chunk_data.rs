@@ -1,10 +1,30 @@
+use std::collections::VecDeque;
 use std::fmt::{self, Debug};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use crate::{CellPosition, ChunkSize};
+use crate::{CellPosition, CellRect, ChunkSize};
 
+/// The ids of every chunk, so no two chunks or clones share one.
+static IDS: AtomicU64 = AtomicU64::new(1);
+
+/// The number of changes `ChunkData::changed_since` remembers.
+const CHANGES: usize = 8;
+
+/// The cells of a chunk, stored one row at a time from the top left.
+///
+/// Cells changed through `get_mut` or any of the bulk writers are
+/// tracked in a dirty rectangle, so the mesher only has to look at the
+/// cells that changed since it last took it. Caches that must not take
+/// it compare the `generation` of the chunk instead, see `changed_since`.
+/// Writing to `data` directly bypasses the tracking.
 pub struct ChunkData<T> {
     pub size: ChunkSize,
     pub data: Vec<T>,
+    dirty: Option<CellRect>,
+    id: u64,
+    generation: u64,
+    /// The latest changes, with the generation after each of them.
+    changes: VecDeque<(u64, CellRect)>,
 }
 
 impl<T> ChunkData<T> {
@@ -14,117 +34,226 @@ impl<T> ChunkData<T> {
     ///
     /// Intended usage is to pass in a closure that executes
     /// some sort of noise function.
-    pub fn new_with_seed<F>(size: ChunkSize, mut seed: F) -> Self
+    pub fn new_with_seed<F>(size: ChunkSize, seed: F) -> Self
     where
         F: FnMut(CellPosition) -> T,
     {
-        let mut data = Vec::with_capacity(size.width * size.height);
+        let data = Self::bounds_of(size).iter().map(seed).collect();
+
+        Self::from_vec(size, data)
+    }
 
-        let left = -(size.width as i32 / 2);
-        let right = size.width as i32 / 2;
-        let top = size.height as i32 / 2;
-        let bottom = -(size.height as i32 / 2);
+    /// Create a chunk from cells stored one row at a time from the top left.
+    pub fn from_vec(size: ChunkSize, data: Vec<T>) -> Self {
+        assert_eq!(size.width * size.height, data.len());
 
-        for x in left..right {
-            for y in top..bottom {
-                data.push(seed((x, y).into()));
-            }
+        Self {
+            size,
+            data,
+            dirty: None,
+            id: IDS.fetch_add(1, Ordering::Relaxed),
+            generation: 0,
+            changes: VecDeque::new(),
         }
+    }
 
-        Self { size, data }
+    fn bounds_of(size: ChunkSize) -> CellRect {
+        CellRect::new(size.top_left(), size.bottom_right())
+    }
+
+    /// The rectangle covering every cell of the chunk.
+    pub fn bounds(&self) -> CellRect {
+        Self::bounds_of(self.size)
     }
 
     /// Iterate over the chunk starting at the top left
     /// going to the bottom right.
     pub fn iter(&self) -> impl Iterator<Item = &T> {
-        let size = self.size;
-        let left = -(size.width as i32 / 2);
-        let right = size.width as i32 / 2;
-        let top = size.height as i32 / 2;
+        self.data.iter()
+    }
 
-        let mut start: CellPosition = (left, top).into();
+    /// Iterate over the chunk starting at the top left
+    /// going to the bottom right.
+    pub fn iter_position(&self) -> impl Iterator<Item = (CellPosition, &T)> {
+        self.bounds().iter().zip(self.data.iter())
+    }
 
-        std::iter::from_fn(move || {
-            if start.y > top {
-                return None;
-            }
+    pub fn get(&self, pos: impl Into<CellPosition>) -> Option<&T> {
+        let pos = pos.into();
 
-            let ret = start;
+        if !self.size.contains(pos) {
+            return None;
+        }
 
-            start.x += 1;
+        self.data.get(self.convert_to_idx(pos))
+    }
 
-            if start.x > right {
-                start.y += 1;
-                start.x = left;
-            }
+    pub fn get_mut(&mut self, pos: impl Into<CellPosition>) -> Option<&mut T> {
+        let pos = pos.into();
 
-            self.get(ret)
-        })
+        if !self.size.contains(pos) {
+            return None;
+        }
+
+        self.mark_dirty(CellRect::cell(pos));
+
+        let idx = self.convert_to_idx(pos);
+        self.data.get_mut(idx)
     }
 
-    /// Iterate over the chunk starting at the top left
-    /// going to the bottom right.
-    pub fn iter_position(&self) -> impl Iterator<Item = (CellPosition, &T)> {
-        let size = self.size;
-        let left = -(size.width as i32 / 2);
-        let right = size.width as i32 / 2;
-        let top = size.height as i32 / 2;
+    /// Iterate mutably over the chunk starting at the top left
+    /// going to the bottom right. Marks the whole chunk as dirty.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.mark_dirty(self.bounds());
 
-        let mut start: CellPosition = (left, top).into();
+        self.data.iter_mut()
+    }
 
-        std::iter::from_fn(move || {
-            if start.y > top {
-                return None;
-            }
+    /// Replace the cell at `pos`, returning the previous value.
+    /// Returns `None` and drops `value` if `pos` is outside the chunk.
+    pub fn set(&mut self, pos: impl Into<CellPosition>, value: T) -> Option<T> {
+        self.get_mut(pos).map(|cell| std::mem::replace(cell, value))
+    }
 
-            let ret = start;
+    /// Mark the cells in `rect` as changed.
+    pub fn mark_dirty(&mut self, rect: CellRect) {
+        let rect = match rect.intersection(self.bounds()) {
+            Some(rect) => rect,
+            None => return,
+        };
 
-            start.x += 1;
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => dirty.union(rect),
+            None => rect,
+        });
 
-            if start.x > right {
-                start.y += 1;
-                start.x = left;
+        self.generation += 1;
+
+        // Changes next to the latest one are merged with it, like strokes of a brush:
+        match self.changes.back_mut() {
+            Some((generation, last)) if touches(*last, rect) => {
+                *generation = self.generation;
+                *last = last.union(rect);
             }
+            _ => {
+                if self.changes.len() == CHANGES {
+                    self.changes.pop_front();
+                }
 
-            self.get(ret).map(|t| (ret, t))
-        })
+                self.changes.push_back((self.generation, rect));
+            }
+        }
     }
 
-    pub fn get(&self, pos: impl Into<CellPosition>) -> Option<&T> {
-        self.data.get(self.convert_to_idx(pos.into()))
+    /// Changes every time cells are marked dirty, whether or not the dirty
+    /// rectangle is taken. Every chunk has an id of its own, clones and
+    /// chunks loaded again in the same place included.
+    pub fn generation(&self) -> ChunkGeneration {
+        ChunkGeneration {
+            id: self.id,
+            count: self.generation,
+        }
     }
 
-    pub fn get_mut(&mut self, pos: impl Into<CellPosition>) -> Option<&mut T> {
-        let idx = self.convert_to_idx(pos.into());
-        self.data.get_mut(idx)
+    /// The rectangle around every cell changed since the chunk was at
+    /// `generation`, or `None` if it has not changed. The whole chunk
+    /// is returned for a generation of another chunk, or one too old
+    /// to be remembered.
+    pub fn changed_since(&self, generation: ChunkGeneration) -> Option<CellRect> {
+        if generation == self.generation() {
+            return None;
+        }
+
+        // Generations of other chunks, or before the oldest change remembered:
+        let since = generation.count;
+        let forgotten = self.changes.len() == CHANGES && since < self.changes[0].0;
+        if generation.id != self.id || since > self.generation || forgotten {
+            return Some(self.bounds());
+        }
+
+        self.changes
+            .iter()
+            .filter(|&&(after, _)| after > since)
+            .map(|&(_, rect)| rect)
+            .fold(None, |changed: Option<CellRect>, rect| {
+                Some(changed.map_or(rect, |changed| changed.union(rect)))
+            })
     }
 
-    /// Convert a `CellPosition` into a valid index for the array.s
+    /// The rectangle around every cell changed since the dirty state was last taken.
+    pub fn dirty(&self) -> Option<CellRect> {
+        self.dirty
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.is_some()
+    }
+
+    /// Take the dirty rectangle, marking the chunk as clean.
+    pub fn take_dirty(&mut self) -> Option<CellRect> {
+        self.dirty.take()
+    }
+
+    /// Convert a `CellPosition` into a valid index for the array.
+    /// The position must be inside the chunk.
     pub const fn convert_to_idx(&self, pos: CellPosition) -> usize {
         let x = pos.x + self.size.width as i32 / 2;
         let y = self.size.height as i32 / 2 - pos.y;
 
-        x as usize * y as usize
+        y as usize * self.size.width + x as usize
     }
 }
 
+/// A point in the changes of one `ChunkData`, see `ChunkData::generation`.
+/// The default generation is not one of any chunk.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ChunkGeneration {
+    /// The chunk the generation is from.
+    pub id: u64,
+    /// The number of times the chunk was marked dirty.
+    pub count: u64,
+}
+
+/// Whether two rectangles overlap or are next to each other.
+fn touches(a: CellRect, b: CellRect) -> bool {
+    let around = CellRect::new(
+        CellPosition::new(a.min.x - 1, a.min.y - 1),
+        CellPosition::new(a.max.x + 1, a.max.y + 1),
+    );
+
+    around.intersection(b).is_some()
+}
+
 impl<T: Default> ChunkData<T> {
     /// Create a chunk using the default value of `T`.
     pub fn new_default(size: ChunkSize) -> Self {
         let mut data = Vec::new();
         data.resize_with(size.width * size.height, Default::default);
 
-        Self { size, data }
+        Self::from_vec(size, data)
     }
 }
 
 impl<T: Clone> ChunkData<T> {
     /// Create a chunk by cloning `e`.
     pub fn new_with(size: ChunkSize, e: T) -> Self {
-        Self {
-            size,
-            data: vec![e; size.width * size.height],
+        Self::from_vec(size, vec![e; size.width * size.height])
+    }
+
+    /// Set every cell in `rect` to `value`, ignoring the
+    /// part of `rect` that is outside the chunk.
+    pub fn fill_rect(&mut self, rect: CellRect, value: T) {
+        let rect = match rect.intersection(self.bounds()) {
+            Some(rect) => rect,
+            None => return,
+        };
+
+        for pos in rect.iter() {
+            let idx = self.convert_to_idx(pos);
+            self.data[idx] = value.clone();
         }
+
+        self.mark_dirty(rect);
     }
 }
 
@@ -133,6 +262,10 @@ impl<T: Clone> Clone for ChunkData<T> {
         Self {
             size: self.size,
             data: self.data.clone(),
+            dirty: self.dirty,
+            id: IDS.fetch_add(1, Ordering::Relaxed),
+            generation: self.generation,
+            changes: VecDeque::new(),
         }
     }
 }
@@ -142,6 +275,123 @@ impl<T: Debug> Debug for ChunkData<T> {
         f.debug_struct("ChunkData")
             .field("size", &self.size)
             .field("data", &self.data)
+            .field("dirty", &self.dirty)
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cell_layout() {
+        let size = ChunkSize::new(4, 3);
+        let chunk = ChunkData::new_with_seed(size, |pos| pos);
+
+        assert_eq!(12, chunk.data.len());
+        assert_eq!(Some(&CellPosition::new(-2, 1)), chunk.iter().next());
+        assert_eq!(Some(&CellPosition::new(1, -1)), chunk.iter().last());
+
+        for (pos, &cell) in chunk.iter_position() {
+            assert_eq!(pos, cell);
+            assert_eq!(Some(&pos), chunk.get(pos));
+        }
+
+        assert_eq!(None, chunk.get((2, 0)));
+        assert_eq!(None, chunk.get((0, 2)));
+        assert_eq!(None, chunk.get((0, -2)));
+    }
+
+    #[test]
+    fn set_cells() {
+        let mut chunk = ChunkData::new_with(ChunkSize::new(4, 4), 0);
+
+        assert_eq!(Some(0), chunk.set((1, -1), 5));
+        assert_eq!(Some(&5), chunk.get((1, -1)));
+        assert_eq!(None, chunk.set((2, 0), 5));
+        assert_eq!(1, chunk.iter().filter(|&&cell| cell == 5).count());
+    }
+
+    #[test]
+    fn dirty_rect() {
+        let mut chunk = ChunkData::new_with(ChunkSize::new(8, 8), 0);
+        assert!(!chunk.is_dirty());
+
+        chunk.set((1, 2), 1);
+        chunk.set((-2, -1), 1);
+        assert_eq!(
+            Some(CellRect::new((-2, -1).into(), (1, 2).into())),
+            chunk.dirty()
+        );
+
+        assert!(chunk.take_dirty().is_some());
+        assert_eq!(None, chunk.dirty());
+
+        chunk.set((10, 0), 1);
+        assert!(!chunk.is_dirty());
+
+        chunk.fill_rect(CellRect::new((2, 2).into(), (6, 6).into()), 2);
+        assert_eq!(
+            Some(CellRect::new((2, 2).into(), (3, 4).into())),
+            chunk.take_dirty()
+        );
+        assert_eq!(6, chunk.iter().filter(|&&cell| cell == 2).count());
+    }
+
+    #[test]
+    fn changes_since_generation() {
+        let mut chunk = ChunkData::new_with(ChunkSize::new(8, 8), 0);
+        let created = chunk.generation();
+        assert_eq!(None, chunk.changed_since(created));
+
+        chunk.set((0, 0), 1);
+        chunk.set((1, 0), 1);
+        let stroke = chunk.generation();
+        chunk.set((-4, 3), 1);
+
+        // Taking the dirty rectangle does not change the generation:
+        assert!(chunk.take_dirty().is_some());
+        assert_eq!(None, chunk.changed_since(chunk.generation()));
+
+        assert_eq!(
+            Some(CellRect::cell((-4, 3).into())),
+            chunk.changed_since(stroke)
+        );
+        assert_eq!(
+            Some(CellRect::new((-4, 0).into(), (1, 3).into())),
+            chunk.changed_since(created)
+        );
+
+        // Another chunk has generations of its own:
+        let other = ChunkData::new_with(ChunkSize::new(8, 8), 0);
+        assert_eq!(Some(other.bounds()), other.changed_since(stroke));
+        assert_eq!(
+            Some(chunk.bounds()),
+            chunk.changed_since(other.generation())
+        );
+
+        // And so do clones, even after the same number of changes:
+        let mut clone = chunk.clone();
+        assert_eq!(
+            Some(clone.bounds()),
+            clone.changed_since(chunk.generation())
+        );
+        chunk.set((0, 0), 2);
+        clone.set((0, 0), 2);
+        assert_eq!(
+            Some(chunk.bounds()),
+            chunk.changed_since(clone.generation())
+        );
+        assert_eq!(
+            Some(clone.bounds()),
+            clone.changed_since(ChunkGeneration::default())
+        );
+
+        // Changes that are too old are forgotten:
+        for i in 0..CHANGES as i32 {
+            chunk.set((i % 4 * 2 - 4, i / 4 * 2 - 3), 1);
+        }
+        assert_eq!(Some(chunk.bounds()), chunk.changed_since(stroke));
+    }
+}
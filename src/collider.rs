@@ -0,0 +1,619 @@
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::sync::Mutex;
+
+use crate::{triangle_area, Contour};
+
+#[cfg(feature = "rapier")]
+pub mod rapier;
+
+#[cfg(feature = "rapier")]
+pub use rapier::*;
+
+/// The shape of a chunk's collider, relative to the center of the chunk.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChunkColliderShape {
+    TriMesh {
+        vertices: Vec<[f32; 2]>,
+        indices: Vec<[u32; 3]>,
+    },
+    /// Convex polygons, counter-clockwise.
+    ConvexPolygons { polygons: Vec<Vec<[f32; 2]>> },
+    /// Static edges with the solid side on their left. Closed
+    /// polylines repeat their first point at the end.
+    Polylines { polylines: Vec<Vec<[f32; 2]>> },
+}
+
+/// How the collider shapes of chunks are built from their meshes.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ColliderDecomposition {
+    /// The triangles of the mesh, as they are.
+    #[default]
+    TriMesh,
+    /// The triangles merged into convex polygons with at most `max_vertices`.
+    Convex { max_vertices: usize },
+    /// The outlines of the mesh, or the contours of the chunk
+    /// for the `ChunkMesher::marching_squares` mesher.
+    Polylines,
+}
+
+impl ChunkColliderShape {
+    /// Build edge colliders from the contours of a chunk, like the ones of
+    /// `MarchingSquares::contours`. Unlike the outlines of its mesh, they
+    /// have no edges along the borders of the chunk where the solid parts
+    /// meet a neighbour, or a neighbour that is not loaded yet.
+    /// Returns `None` if there are no contours.
+    pub fn from_contours(contours: &[Contour]) -> Option<Self> {
+        let polylines: Vec<Vec<[f32; 2]>> = contours
+            .iter()
+            .filter(|contour| contour.points.len() > 1)
+            .map(|contour| {
+                let mut points: Vec<[f32; 2]> = contour.points.iter().map(|p| [p.x, p.y]).collect();
+                if contour.closed {
+                    points.push(points[0]);
+                }
+                points
+            })
+            .collect();
+
+        if polylines.is_empty() {
+            return None;
+        }
+
+        Some(ChunkColliderShape::Polylines { polylines })
+    }
+
+    /// Build a triangle mesh collider from the positions and indices of a
+    /// triangle list mesh. Returns `None` if the mesh has no triangles.
+    pub fn from_mesh(mesh: &Mesh) -> Option<Self> {
+        Self::from_mesh_with(mesh, ColliderDecomposition::TriMesh)
+    }
+
+    /// Build a collider from the triangles of a triangle list mesh, with
+    /// counter-clockwise triangles like the ones of `MarchingSquares`.
+    /// Returns `None` if the mesh has no triangles.
+    pub fn from_mesh_with(mesh: &Mesh, decomposition: ColliderDecomposition) -> Option<Self> {
+        let vertices: Vec<[f32; 2]> = match mesh.attribute(Mesh::ATTRIBUTE_POSITION)? {
+            VertexAttributeValues::Float2(positions) => positions.clone(),
+            VertexAttributeValues::Float3(positions) => {
+                positions.iter().map(|&[x, y, _]| [x, y]).collect()
+            }
+            _ => return None,
+        };
+
+        let indices: Vec<u32> = match mesh.indices() {
+            Some(Indices::U16(indices)) => indices.iter().map(|&i| i as u32).collect(),
+            Some(Indices::U32(indices)) => indices.clone(),
+            None => (0..vertices.len() as u32).collect(),
+        };
+
+        let indices: Vec<[u32; 3]> = indices
+            .chunks_exact(3)
+            .map(|tri| [tri[0], tri[1], tri[2]])
+            .collect();
+
+        if indices.is_empty() {
+            return None;
+        }
+
+        Some(match decomposition {
+            ColliderDecomposition::TriMesh => ChunkColliderShape::TriMesh { vertices, indices },
+            ColliderDecomposition::Convex { max_vertices } => ChunkColliderShape::ConvexPolygons {
+                polygons: convex_polygons(&vertices, &indices, max_vertices),
+            },
+            ColliderDecomposition::Polylines => ChunkColliderShape::Polylines {
+                polylines: outlines(&vertices, &indices),
+            },
+        })
+    }
+}
+
+/// Merge neighbouring triangles into convex polygons of at most
+/// `max_vertices` (at least 3), greedily removing the edges between them
+/// like Hertel-Mehlhorn. Collinear points are left out of the polygons.
+fn convex_polygons(
+    vertices: &[[f32; 2]],
+    indices: &[[u32; 3]],
+    max_vertices: usize,
+) -> Vec<Vec<[f32; 2]>> {
+    let max_vertices = max_vertices.max(3);
+
+    // The polygons keep their collinear points while merging, so the
+    // edges they share with their neighbours still match:
+    let mut polygons: Vec<Option<Vec<u32>>> = indices
+        .iter()
+        .filter(|&&[a, b, c]| {
+            triangle_area([
+                vertices[a as usize],
+                vertices[b as usize],
+                vertices[c as usize],
+            ]) > f32::EPSILON
+        })
+        .map(|tri| Some(tri.to_vec()))
+        .collect();
+
+    let mut edges: HashMap<(u32, u32), usize> = HashMap::new();
+    for (i, polygon) in polygons.iter().enumerate() {
+        for edge in polygon_edges(polygon.as_ref().unwrap()) {
+            edges.insert(edge, i);
+        }
+    }
+
+    for i in 0..polygons.len() {
+        let mut merged = true;
+
+        while merged {
+            merged = false;
+            let polygon = match &polygons[i] {
+                Some(polygon) => polygon.clone(),
+                None => break,
+            };
+
+            for (a, b) in polygon_edges(&polygon) {
+                let j = match edges.get(&(b, a)) {
+                    Some(&j) if j != i => j,
+                    _ => continue,
+                };
+
+                let other = polygons[j].as_ref().unwrap();
+                let joined = join_polygons(&polygon, other, a, b);
+                let corners = corners(vertices, &joined);
+
+                if corners.len() > max_vertices || !is_convex(vertices, &joined) {
+                    continue;
+                }
+
+                for edge in polygon_edges(&polygon).chain(polygon_edges(other)) {
+                    edges.remove(&edge);
+                }
+                for edge in polygon_edges(&joined) {
+                    edges.insert(edge, i);
+                }
+
+                polygons[i] = Some(joined);
+                polygons[j] = None;
+                merged = true;
+                break;
+            }
+        }
+    }
+
+    polygons
+        .into_iter()
+        .flatten()
+        .map(|polygon| {
+            corners(vertices, &polygon)
+                .into_iter()
+                .map(|v| vertices[v as usize])
+                .collect()
+        })
+        .collect()
+}
+
+/// The edges of a polygon, in order.
+fn polygon_edges(polygon: &[u32]) -> impl Iterator<Item = (u32, u32)> + '_ {
+    (0..polygon.len()).map(move |i| (polygon[i], polygon[(i + 1) % polygon.len()]))
+}
+
+/// Join two polygons along their shared edge, from `a` to `b` in `polygon`.
+fn join_polygons(polygon: &[u32], other: &[u32], a: u32, b: u32) -> Vec<u32> {
+    let rotate = |polygon: &[u32], start: u32| {
+        let i = polygon.iter().position(|&v| v == start).unwrap();
+        let mut rotated = polygon[i..].to_vec();
+        rotated.extend_from_slice(&polygon[..i]);
+        rotated
+    };
+
+    // `polygon` from `b` around to `a`, then `other` from after `a` to before `b`:
+    let mut joined = rotate(polygon, b);
+    let other = rotate(other, a);
+    joined.extend_from_slice(&other[1..other.len() - 1]);
+
+    // Polygons that share several edges in a row leave spikes
+    // going out to the inner points and back, remove them:
+    let mut i = 0;
+    while joined.len() > 3 && i < joined.len() {
+        let n = joined.len();
+
+        if joined[(i + n - 1) % n] == joined[(i + 1) % n] {
+            let next = (i + 1) % n;
+            joined.remove(i.max(next));
+            joined.remove(i.min(next));
+            i = 0;
+        } else {
+            i += 1;
+        }
+    }
+
+    joined
+}
+
+/// The turn at every point of a polygon, positive when it turns left.
+fn turns<'a>(vertices: &'a [[f32; 2]], polygon: &'a [u32]) -> impl Iterator<Item = f32> + 'a {
+    let n = polygon.len();
+
+    (0..n).map(move |i| {
+        let [a, b, c] = [
+            vertices[polygon[(i + n - 1) % n] as usize],
+            vertices[polygon[i] as usize],
+            vertices[polygon[(i + 1) % n] as usize],
+        ];
+        triangle_area([a, b, c])
+    })
+}
+
+/// The points of a polygon that are not collinear with their neighbours.
+fn corners(vertices: &[[f32; 2]], polygon: &[u32]) -> Vec<u32> {
+    polygon
+        .iter()
+        .zip(turns(vertices, polygon))
+        .filter(|&(_, turn)| turn.abs() > f32::EPSILON)
+        .map(|(&v, _)| v)
+        .collect()
+}
+
+fn is_convex(vertices: &[[f32; 2]], polygon: &[u32]) -> bool {
+    turns(vertices, polygon).all(|turn| turn >= -f32::EPSILON)
+}
+
+/// The edges of the mesh that are not shared by two triangles, joined
+/// into polylines with the inside of the mesh on their left. A vertex
+/// where several outlines meet is visited once for each of them.
+fn outlines(vertices: &[[f32; 2]], indices: &[[u32; 3]]) -> Vec<Vec<[f32; 2]>> {
+    let edges: HashSet<(u32, u32)> = indices
+        .iter()
+        .flat_map(|&[a, b, c]| vec![(a, b), (b, c), (c, a)])
+        .collect();
+
+    let mut boundary: Vec<(u32, u32)> = edges
+        .iter()
+        .filter(|&&(a, b)| !edges.contains(&(b, a)))
+        .copied()
+        .collect();
+    boundary.sort_unstable();
+
+    let mut next: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut incoming: HashMap<u32, usize> = HashMap::new();
+    for &(a, b) in boundary.iter().rev() {
+        next.entry(a).or_default().push(b);
+        *incoming.entry(b).or_default() += 1;
+    }
+
+    // Open outlines start where more edges leave a vertex than reach it,
+    // the edges left after them are closed loops:
+    let open = boundary
+        .iter()
+        .map(|&(a, _)| a)
+        .filter(|a| next[a].len() > incoming.get(a).copied().unwrap_or(0));
+    let starts: Vec<u32> = open.chain(boundary.iter().map(|&(a, _)| a)).collect();
+
+    let mut polylines = Vec::new();
+    for start in starts {
+        let mut polyline = vec![vertices[start as usize]];
+        let mut v = start;
+
+        while let Some(link) = next.get_mut(&v).and_then(Vec::pop) {
+            polyline.push(vertices[link as usize]);
+            v = link;
+        }
+
+        if polyline.len() > 1 {
+            polylines.push(polyline);
+        }
+    }
+
+    polylines
+}
+
+/// Creates, updates and removes the colliders of chunks for a
+/// physics engine. `translation` is the center of the chunk in the world.
+pub trait ChunkColliderBackend: Send + Sync + 'static {
+    fn create(
+        &self,
+        commands: &mut Commands,
+        entity: Entity,
+        translation: Vec2,
+        shape: &ChunkColliderShape,
+    );
+
+    /// Replace the collider of a chunk that changed. Removes the
+    /// old collider and creates a new one by default.
+    fn update(
+        &self,
+        commands: &mut Commands,
+        entity: Entity,
+        translation: Vec2,
+        shape: &ChunkColliderShape,
+    ) {
+        self.remove(commands, entity);
+        self.create(commands, entity, translation, shape);
+    }
+
+    /// Called before the chunk entity is despawned.
+    fn remove(&self, commands: &mut Commands, entity: Entity);
+
+    /// Move the collider of a chunk after the `FloatingOrigin` moved.
+    /// Does nothing by default, for colliders that follow `Transform`s.
+    fn set_translation(&self, _commands: &mut Commands, _entity: Entity, _translation: Vec2) {}
+}
+
+/// The collider backend used for the chunks of the layer `L`,
+/// and how their shapes are built from the chunk meshes.
+pub struct ChunkColliders<L> {
+    pub backend: Box<dyn ChunkColliderBackend>,
+    pub decomposition: ColliderDecomposition,
+    marker: PhantomData<fn() -> L>,
+}
+
+impl<L> ChunkColliders<L> {
+    pub fn new(backend: impl ChunkColliderBackend) -> Self {
+        Self {
+            backend: Box::new(backend),
+            decomposition: ColliderDecomposition::default(),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn with_decomposition(mut self, decomposition: ColliderDecomposition) -> Self {
+        self.decomposition = decomposition;
+        self
+    }
+}
+
+impl<L> Default for ChunkColliders<L> {
+    fn default() -> Self {
+        Self::new(NoColliders)
+    }
+}
+
+/// Marks a chunk that had a collider created by the `ChunkColliders` backend.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ChunkCollider;
+
+/// A backend that never creates any colliders.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct NoColliders;
+
+impl ChunkColliderBackend for NoColliders {
+    fn create(&self, _: &mut Commands, _: Entity, _: Vec2, _: &ChunkColliderShape) {}
+
+    fn remove(&self, _: &mut Commands, _: Entity) {}
+}
+
+/// A call made to a `RecordedColliders` backend.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColliderCall {
+    Create(Entity, Vec2, ChunkColliderShape),
+    Update(Entity, Vec2, ChunkColliderShape),
+    Remove(Entity),
+    SetTranslation(Entity, Vec2),
+}
+
+/// A backend that only records the calls made to it, for tests.
+#[derive(Debug, Default)]
+pub struct RecordedColliders {
+    calls: Mutex<Vec<ColliderCall>>,
+}
+
+impl RecordedColliders {
+    /// Take every call recorded so far.
+    pub fn take(&self) -> Vec<ColliderCall> {
+        std::mem::take(&mut *self.calls.lock().unwrap())
+    }
+
+    fn record(&self, call: ColliderCall) {
+        self.calls.lock().unwrap().push(call);
+    }
+}
+
+impl ChunkColliderBackend for RecordedColliders {
+    fn create(
+        &self,
+        _: &mut Commands,
+        entity: Entity,
+        translation: Vec2,
+        shape: &ChunkColliderShape,
+    ) {
+        self.record(ColliderCall::Create(entity, translation, shape.clone()));
+    }
+
+    fn update(
+        &self,
+        _: &mut Commands,
+        entity: Entity,
+        translation: Vec2,
+        shape: &ChunkColliderShape,
+    ) {
+        self.record(ColliderCall::Update(entity, translation, shape.clone()));
+    }
+
+    fn remove(&self, _: &mut Commands, entity: Entity) {
+        self.record(ColliderCall::Remove(entity));
+    }
+
+    fn set_translation(&self, _: &mut Commands, entity: Entity, translation: Vec2) {
+        self.record(ColliderCall::SetTranslation(entity, translation));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::pipeline::PrimitiveTopology;
+
+    use crate::{CellRect, CellSize, ChunkData, ChunkSize, MarchingSquares};
+
+    #[test]
+    fn shape_from_mesh() {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [0.0, 1.0, 0.0],
+            ],
+        );
+        mesh.set_indices(Some(Indices::U32(vec![0, 1, 2, 0, 2, 3])));
+
+        assert_eq!(
+            Some(ChunkColliderShape::TriMesh {
+                vertices: vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+                indices: vec![[0, 1, 2], [0, 2, 3]],
+            }),
+            ChunkColliderShape::from_mesh(&mesh)
+        );
+
+        let empty = Mesh::new(PrimitiveTopology::TriangleList);
+        assert_eq!(None, ChunkColliderShape::from_mesh(&empty));
+    }
+
+    fn square_mesh(size: ChunkSize, solid: &[(i32, i32)]) -> Mesh {
+        let mut data = ChunkData::new_with(size, 0.0f32);
+        for &pos in solid {
+            data.set(pos, 1.0);
+        }
+
+        MarchingSquares::new(0.5).mesh(&data, CellSize::new(1, 1), |&cell| cell)
+    }
+
+    fn polygon_area(points: &[[f32; 2]]) -> f32 {
+        (1..points.len() - 1)
+            .map(|i| triangle_area([points[0], points[i], points[i + 1]]))
+            .sum()
+    }
+
+    #[test]
+    fn convex_decomposition() {
+        let size = ChunkSize::new(4, 4);
+        let solid: Vec<(i32, i32)> = CellRect::new((-2, 2).into(), (1, -1).into())
+            .iter()
+            .filter(|pos| (pos.x, pos.y) != (0, 0))
+            .map(|pos| (pos.x, pos.y))
+            .collect();
+        let mesh = square_mesh(size, &solid);
+        let (area, triangles): (f32, usize) = match ChunkColliderShape::from_mesh(&mesh) {
+            Some(ChunkColliderShape::TriMesh { vertices, indices }) => (
+                indices
+                    .iter()
+                    .map(|&[a, b, c]| {
+                        triangle_area([
+                            vertices[a as usize],
+                            vertices[b as usize],
+                            vertices[c as usize],
+                        ])
+                    })
+                    .sum(),
+                indices.len(),
+            ),
+            _ => panic!(),
+        };
+
+        for &max_vertices in &[3, 4, 8] {
+            let convex = ColliderDecomposition::Convex { max_vertices };
+            let polygons = match ChunkColliderShape::from_mesh_with(&mesh, convex) {
+                Some(ChunkColliderShape::ConvexPolygons { polygons }) => polygons,
+                _ => panic!(),
+            };
+
+            assert!(polygons.len() < triangles);
+
+            for polygon in &polygons {
+                assert!(polygon.len() >= 3 && polygon.len() <= max_vertices);
+
+                let n = polygon.len();
+                for i in 0..n {
+                    let turn =
+                        triangle_area([polygon[i], polygon[(i + 1) % n], polygon[(i + 2) % n]]);
+                    assert!(turn > 0.0);
+                }
+            }
+
+            let total: f32 = polygons.iter().map(|polygon| polygon_area(polygon)).sum();
+            assert!((total - area).abs() < 1e-4);
+        }
+
+        // A solid square of cells is a single octagon:
+        let mesh = square_mesh(size, &[(-1, 1), (0, 1), (-1, 0), (0, 0)]);
+        let convex = ColliderDecomposition::Convex { max_vertices: 8 };
+        match ChunkColliderShape::from_mesh_with(&mesh, convex) {
+            Some(ChunkColliderShape::ConvexPolygons { polygons }) => {
+                assert_eq!(1, polygons.len());
+                assert_eq!(8, polygons[0].len());
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn outline_polylines() {
+        let mesh = square_mesh(ChunkSize::new(4, 4), &[(0, 0)]);
+
+        match ChunkColliderShape::from_mesh_with(&mesh, ColliderDecomposition::Polylines) {
+            Some(ChunkColliderShape::Polylines { polylines }) => {
+                assert_eq!(1, polylines.len());
+                assert_eq!(5, polylines[0].len());
+                assert_eq!(polylines[0][0], polylines[0][4]);
+                assert!((polygon_area(&polylines[0]) - 0.5).abs() < 1e-5);
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn outlines_through_shared_vertices() {
+        // Two triangles touching at a single vertex:
+        let vertices = [
+            [0.0, 0.0],
+            [1.0, 0.0],
+            [1.0, 1.0],
+            [-1.0, 0.0],
+            [-1.0, -1.0],
+        ];
+        let polylines = outlines(&vertices, &[[0, 1, 2], [0, 3, 4]]);
+
+        let edges: usize = polylines.iter().map(|polyline| polyline.len() - 1).sum();
+        assert_eq!(6, edges);
+        for polyline in &polylines {
+            assert_eq!(polyline[0], polyline[polyline.len() - 1]);
+        }
+    }
+
+    #[test]
+    fn contour_polylines() {
+        // The solid column along the right border of a chunk
+        // without a neighbour to its right:
+        let size = ChunkSize::new(4, 4);
+        let solid: Vec<(i32, i32)> = (-1..=2).map(|y| (1, y)).collect();
+
+        let mesh = square_mesh(size, &solid);
+        let outlines =
+            match ChunkColliderShape::from_mesh_with(&mesh, ColliderDecomposition::Polylines) {
+                Some(ChunkColliderShape::Polylines { polylines }) => polylines,
+                _ => panic!(),
+            };
+        assert!(outlines.iter().flatten().any(|&[x, _]| x == 1.0));
+
+        let mut data = ChunkData::new_with(size, 0.0f32);
+        for &pos in &solid {
+            data.set(pos, 1.0);
+        }
+        let contours = MarchingSquares::new(0.5).contours(&data, CellSize::new(1, 1), |&cell| cell);
+
+        match ChunkColliderShape::from_contours(&contours) {
+            Some(ChunkColliderShape::Polylines { polylines }) => {
+                assert_eq!(1, polylines.len());
+                assert!(polylines[0].len() > 1);
+                assert_ne!(polylines[0][0], polylines[0][polylines[0].len() - 1]);
+                assert!(polylines[0].iter().all(|&[x, _]| x == 0.5));
+            }
+            _ => panic!(),
+        }
+
+        assert_eq!(None, ChunkColliderShape::from_contours(&[]));
+    }
+}
@@ -0,0 +1,113 @@
+use bevy::ecs::Command;
+use bevy::prelude::*;
+
+use bevy_rapier2d::{
+    physics::{ColliderHandleComponent, RigidBodyHandleComponent},
+    rapier::{
+        dynamics::{JointSet, RigidBodyBuilder, RigidBodySet},
+        geometry::{ColliderBuilder, ColliderSet, ColliderShape},
+        math::{Isometry, Point},
+        na::Point3,
+    },
+};
+
+use crate::{ChunkColliderBackend, ChunkColliderShape};
+
+/// Gives chunks a static rigid body with a collider for their shape.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RapierColliders;
+
+impl ChunkColliderBackend for RapierColliders {
+    fn create(
+        &self,
+        commands: &mut Commands,
+        entity: Entity,
+        translation: Vec2,
+        shape: &ChunkColliderShape,
+    ) {
+        let body = RigidBodyBuilder::new_static().translation(translation.x, translation.y);
+
+        let collider = match shape {
+            ChunkColliderShape::TriMesh { vertices, indices } => ColliderBuilder::trimesh(
+                vertices.iter().map(|&[x, y]| Point::new(x, y)).collect(),
+                indices
+                    .iter()
+                    .map(|&[a, b, c]| Point3::new(a, b, c))
+                    .collect(),
+            ),
+            ChunkColliderShape::ConvexPolygons { polygons } => ColliderBuilder::compound(
+                polygons
+                    .iter()
+                    .filter_map(|polygon| {
+                        let points = polygon.iter().map(|&[x, y]| Point::new(x, y)).collect();
+                        ColliderShape::convex_polyline(points)
+                    })
+                    .map(|shape| (Isometry::identity(), shape))
+                    .collect(),
+            ),
+            ChunkColliderShape::Polylines { polylines } => {
+                let mut vertices = Vec::new();
+                let mut indices = Vec::new();
+
+                for polyline in polylines {
+                    let start = vertices.len() as u32;
+                    vertices.extend(polyline.iter().map(|&[x, y]| Point::new(x, y)));
+                    indices.extend((start + 1..vertices.len() as u32).map(|i| [i - 1, i]));
+                }
+
+                ColliderBuilder::polyline(vertices, Some(indices))
+            }
+        };
+
+        commands.insert(entity, (body, collider));
+    }
+
+    fn remove(&self, commands: &mut Commands, entity: Entity) {
+        commands.add_command(RemoveRigidBody(entity));
+    }
+
+    fn set_translation(&self, commands: &mut Commands, entity: Entity, translation: Vec2) {
+        commands.add_command(MoveRigidBody(entity, translation));
+    }
+}
+
+/// Static bodies do not follow their `Transform`, so they
+/// are moved by hand when the floating origin moves.
+struct MoveRigidBody(Entity, Vec2);
+
+impl Command for MoveRigidBody {
+    fn write(self: Box<Self>, world: &mut World, resources: &mut Resources) {
+        let handle = match world.get::<RigidBodyHandleComponent>(self.0) {
+            Ok(handle) => handle.handle(),
+            Err(_) => return,
+        };
+
+        let mut bodies = resources.get_mut::<RigidBodySet>().unwrap();
+
+        if let Some(body) = bodies.get_mut(handle) {
+            body.set_position(Isometry::translation(self.1.x, self.1.y), true);
+        }
+    }
+}
+
+/// Despawning an entity does not remove its body from the `RigidBodySet`,
+/// so it is removed by hand along with its colliders.
+struct RemoveRigidBody(Entity);
+
+impl Command for RemoveRigidBody {
+    fn write(self: Box<Self>, world: &mut World, resources: &mut Resources) {
+        let handle = match world.get::<RigidBodyHandleComponent>(self.0) {
+            Ok(handle) => handle.handle(),
+            Err(_) => return,
+        };
+
+        let mut bodies = resources.get_mut::<RigidBodySet>().unwrap();
+        let mut colliders = resources.get_mut::<ColliderSet>().unwrap();
+        let mut joints = resources.get_mut::<JointSet>().unwrap();
+
+        bodies.remove(handle, &mut colliders, &mut joints);
+
+        let _ = world.remove_one::<RigidBodyHandleComponent>(self.0);
+        let _ = world.remove_one::<ColliderHandleComponent>(self.0);
+    }
+}
@@ -0,0 +1,370 @@
+use bevy::prelude::Vec2;
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use crate::{CellSize, ChunkPosition, ChunkSize, GlobalCellPosition, VertexKey};
+
+/// A polyline along the border between the solid and empty parts of a
+/// chunk, with the solid side on its left. Closed contours around solid
+/// areas are counter-clockwise, and the ones around holes are clockwise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contour {
+    pub points: Vec<Vec2>,
+    /// Whether the last point connects back to the first.
+    pub closed: bool,
+    /// The vertices at both ends of an open contour, to stitch it
+    /// with the contours of the neighbouring chunks.
+    ends: Option<(VertexKey, VertexKey)>,
+}
+
+impl Contour {
+    pub fn new(points: Vec<Vec2>, closed: bool) -> Self {
+        Self {
+            points,
+            closed,
+            ends: None,
+        }
+    }
+
+    /// The area enclosed by the contour, positive when it is counter-clockwise.
+    /// Open contours are closed with a straight line.
+    pub fn signed_area(&self) -> f32 {
+        let n = self.points.len();
+
+        (0..n)
+            .map(|i| {
+                let (a, b) = (self.points[i], self.points[(i + 1) % n]);
+                a.x * b.y - b.x * a.y
+            })
+            .sum::<f32>()
+            / 2.0
+    }
+
+    /// Whether the contour is closed around an empty area.
+    pub fn is_hole(&self) -> bool {
+        self.closed && self.signed_area() < 0.0
+    }
+
+    /// Remove the points that are at most `epsilon` away from the simplified
+    /// contour (Douglas-Peucker). Open contours keep both of their ends.
+    pub fn simplify(&mut self, epsilon: f32) {
+        if self.points.len() < 3 {
+            return;
+        }
+
+        if !self.closed {
+            self.points = douglas_peucker(&self.points, epsilon);
+            return;
+        }
+
+        // Split the loop at the point farthest from the first one,
+        // and simplify both halves with their ends fixed:
+        let first = self.points[0];
+        let far = (0..self.points.len())
+            .max_by(|&a, &b| {
+                let a = (self.points[a] - first).length();
+                let b = (self.points[b] - first).length();
+                a.partial_cmp(&b).unwrap()
+            })
+            .unwrap_or(0);
+
+        let mut back = self.points[far..].to_vec();
+        back.push(first);
+
+        let mut points = douglas_peucker(&self.points[..=far], epsilon);
+        let back = douglas_peucker(&back, epsilon);
+        points.pop();
+        points.extend_from_slice(&back[..back.len() - 1]);
+
+        self.points = points;
+    }
+}
+
+/// The points of `points` that are farther than `epsilon`
+/// from the line between its ends, along with both ends.
+fn douglas_peucker(points: &[Vec2], epsilon: f32) -> Vec<Vec2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let farthest = (1..points.len() - 1)
+        .map(|i| (i, segment_distance(points[i], first, last)))
+        .fold(
+            (0, 0.0),
+            |far, next| if next.1 > far.1 { next } else { far },
+        );
+
+    match farthest {
+        (i, distance) if distance > epsilon => {
+            let mut simplified = douglas_peucker(&points[..=i], epsilon);
+            simplified.pop();
+            simplified.extend(douglas_peucker(&points[i..], epsilon));
+            simplified
+        }
+        _ => vec![first, last],
+    }
+}
+
+/// The distance from `p` to the segment between `a` and `b`.
+fn segment_distance(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let length = ab.length_squared();
+
+    if length <= f32::EPSILON {
+        return (p - a).length();
+    }
+
+    let t = ((p - a).dot(ab) / length).clamp(0.0, 1.0);
+    (p - (a + ab * t)).length()
+}
+
+/// The contour segments of the squares of a chunk, keyed
+/// by the vertices at their start and end.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ContourSegments {
+    next: HashMap<VertexKey, VertexKey>,
+    points: HashMap<VertexKey, Vec2>,
+}
+
+impl ContourSegments {
+    pub(crate) fn insert(&mut self, (a, from): (VertexKey, Vec2), (b, to): (VertexKey, Vec2)) {
+        self.next.insert(a, b);
+        self.points.insert(a, from);
+        self.points.insert(b, to);
+    }
+
+    /// Remove the segment starting at `a`.
+    pub(crate) fn remove(&mut self, a: VertexKey) {
+        self.next.remove(&a);
+    }
+
+    /// Join the segments into contours.
+    pub(crate) fn chain(&self) -> Vec<Contour> {
+        chain_links(&self.next)
+            .into_iter()
+            .map(|(keys, closed)| Contour {
+                points: keys.iter().map(|key| self.points[key]).collect(),
+                closed,
+                ends: if closed {
+                    None
+                } else {
+                    Some((keys[0], keys[keys.len() - 1]))
+                },
+            })
+            .collect()
+    }
+}
+
+/// Follow the links from each key to the next one, starting with the keys
+/// nothing links to. Returns the keys of each chain, and whether it loops.
+pub(crate) fn chain_links<K>(next: &HashMap<K, K>) -> Vec<(Vec<K>, bool)>
+where
+    K: Copy + Ord + Hash,
+{
+    let linked: HashSet<K> = next.values().copied().collect();
+    let mut keys: Vec<K> = next.keys().copied().collect();
+    keys.sort();
+
+    let starts = keys.iter().filter(|key| !linked.contains(key));
+    let loops = keys.iter().filter(|key| linked.contains(key));
+
+    let mut visited = HashSet::new();
+    let mut chains = Vec::new();
+
+    for (&start, closed) in starts
+        .map(|key| (key, false))
+        .chain(loops.map(|key| (key, true)))
+    {
+        if !visited.insert(start) {
+            continue;
+        }
+
+        let mut chain = vec![start];
+        let mut key = start;
+
+        while let Some(&link) = next.get(&key) {
+            if !visited.insert(link) {
+                break;
+            }
+
+            chain.push(link);
+            key = link;
+        }
+
+        chains.push((chain, closed));
+    }
+
+    chains
+}
+
+/// The contours of every chunk of a world, so the open contours
+/// along the borders of chunks can be joined together.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkContours {
+    pub chunks: HashMap<ChunkPosition, Vec<Contour>>,
+}
+
+impl ChunkContours {
+    /// Replace the contours of the chunk at `position`.
+    pub fn insert(
+        &mut self,
+        position: ChunkPosition,
+        contours: Vec<Contour>,
+    ) -> Option<Vec<Contour>> {
+        self.chunks.insert(position, contours)
+    }
+
+    pub fn remove(&mut self, position: ChunkPosition) -> Option<Vec<Contour>> {
+        self.chunks.remove(&position)
+    }
+
+    /// Every contour in world coordinates, with the open contours of
+    /// neighbouring chunks joined together. Contours can still be open
+    /// where a neighbouring chunk is missing.
+    pub fn stitched(&self, chunk_size: ChunkSize, cell_size: CellSize) -> Vec<Contour> {
+        let mut positions: Vec<_> = self.chunks.keys().copied().collect();
+        positions.sort();
+
+        let mut stitched = Vec::new();
+        let mut pieces: HashMap<(u8, GlobalCellPosition), Vec<Vec2>> = HashMap::new();
+        let mut next = HashMap::new();
+
+        for position in positions {
+            let offset = position.to_world(chunk_size, cell_size);
+
+            for contour in &self.chunks[&position] {
+                let points = contour.points.iter().map(|&point| point + offset).collect();
+
+                match contour.ends {
+                    Some((start, end)) => {
+                        let start = start.to_global(chunk_size, position);
+                        let end = end.to_global(chunk_size, position);
+
+                        next.insert(start, end);
+                        pieces.insert(start, points);
+                    }
+                    None => stitched.push(Contour::new(points, contour.closed)),
+                }
+            }
+        }
+
+        for (keys, closed) in chain_links(&next) {
+            // The last key of an open chain is the end of the last piece:
+            let starts = if closed {
+                &keys[..]
+            } else {
+                &keys[..keys.len() - 1]
+            };
+
+            let mut points: Vec<Vec2> = Vec::new();
+
+            for start in starts {
+                let piece = &pieces[start];
+                let skip = if points.is_empty() { 0 } else { 1 };
+                points.extend_from_slice(&piece[skip..]);
+            }
+
+            if closed {
+                points.pop();
+            }
+
+            stitched.push(Contour::new(points, closed));
+        }
+
+        stitched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{ChunkData, ChunkView, MarchingSquares};
+
+    #[test]
+    fn solids_and_holes() {
+        let size = ChunkSize::new(4, 4);
+        let cell_size = CellSize::new(1, 1);
+        let marching = MarchingSquares::new(0.5);
+
+        let mut data = ChunkData::new_with(size, 0.0f32);
+        data.set((0, 0), 1.0);
+
+        let contours = marching.contours(&data, cell_size, |&cell| cell);
+        assert_eq!(1, contours.len());
+        assert!(contours[0].closed);
+        assert!(!contours[0].is_hole());
+        assert_eq!(4, contours[0].points.len());
+        assert!((contours[0].signed_area() - 0.5).abs() < 1e-5);
+
+        let mut data = ChunkData::new_with(size, 1.0f32);
+        data.set((0, 0), 0.0);
+
+        let contours = marching.contours(&data, cell_size, |&cell| cell);
+        assert_eq!(1, contours.len());
+        assert!(contours[0].is_hole());
+    }
+
+    #[test]
+    fn stitch_across_chunks() {
+        let size = ChunkSize::new(4, 4);
+        let cell_size = CellSize::new(1, 1);
+        let marching = MarchingSquares::new(0.5);
+
+        let band = ChunkData::new_with_seed(size, |pos| if pos.y >= 0 { 1.0f32 } else { 0.0 });
+        let mut view = ChunkView::new(&band);
+        view.set_neighbour((1, 0), Some(&band));
+
+        let mut contours = ChunkContours::default();
+        contours.insert(
+            ChunkPosition::new(0, 0),
+            marching.contours(view, cell_size, |&cell| cell),
+        );
+        contours.insert(
+            ChunkPosition::new(1, 0),
+            marching.contours(&band, cell_size, |&cell| cell),
+        );
+
+        let mut stitched = contours.stitched(size, cell_size);
+        assert_eq!(1, stitched.len());
+        assert!(!stitched[0].closed);
+        assert_eq!(8, stitched[0].points.len());
+
+        // Solid is above, on the left of a contour going right:
+        assert_eq!(Vec2::new(-2.0, -0.5), stitched[0].points[0]);
+        assert_eq!(Vec2::new(5.0, -0.5), stitched[0].points[7]);
+
+        stitched[0].simplify(0.01);
+        assert_eq!(
+            vec![Vec2::new(-2.0, -0.5), Vec2::new(5.0, -0.5)],
+            stitched[0].points
+        );
+    }
+
+    #[test]
+    fn simplify_closed_contours() {
+        let mut square = Contour::new(
+            vec![
+                Vec2::new(0.0, 0.0),
+                Vec2::new(1.0, 0.0),
+                Vec2::new(2.0, 0.0),
+                Vec2::new(2.0, 1.0),
+                Vec2::new(2.0, 2.0),
+                Vec2::new(1.0, 2.05),
+                Vec2::new(0.0, 2.0),
+                Vec2::new(0.0, 1.0),
+            ],
+            true,
+        );
+        let area = square.signed_area();
+
+        square.simplify(0.1);
+        assert_eq!(4, square.points.len());
+        assert!((square.signed_area() - area).abs() < 0.1);
+
+        square.simplify(0.01);
+        assert_eq!(4, square.points.len());
+    }
+}
@@ -0,0 +1,449 @@
+use bevy::prelude::*;
+
+use std::collections::HashMap;
+
+use crate::{triangle_area, CellPosition, CellSize, ChunkView, ContourMesh, UvMapping};
+
+/// Builds chunk meshes with dual contouring, keeping the sharp corners
+/// and straight walls that marching squares rounds off.
+///
+/// Every square between cell centers that the contour crosses gets a
+/// single vertex, placed where the lines through the crossings on its
+/// edges, along their normals, meet best. Normals are interpolated from
+/// the gradient of every cell, or given exactly by a Hermite function.
+///
+/// Like `MarchingSquares`, a chunk owns the squares whose top left corner
+/// is one of its cells, and the vertices of a square only depend on its
+/// own corners, so neighbouring chunks place the vertices they share on
+/// the same points. The gaps between the squares of two chunks are
+/// closed by the chunk on the right or below, once it has the cells of
+/// its neighbours in its `ChunkView`. So it samples all of
+/// `ChunkView::NEIGHBOURS`, which its `ChunkMesher` must be given.
+///
+/// A square with two opposite solid corners gets a single vertex, so it
+/// always joins them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DualContouring {
+    pub threshold: f32,
+    /// How strongly vertices are pulled towards the average of their
+    /// crossings, keeping them in place along straight walls.
+    pub bias: f32,
+    pub uvs: UvMapping,
+}
+
+/// A vertex shared between neighbouring squares: a cell center,
+/// or the vertex of the square with its top left corner at a cell.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+enum DualKey {
+    Cell(CellPosition),
+    Square(CellPosition),
+}
+
+/// The vertex of a square, relative to the center of the chunk,
+/// along with the cell it belongs to.
+type SquareVertex = (Vec2, CellPosition);
+
+impl DualContouring {
+    pub fn new(threshold: f32) -> Self {
+        Self {
+            threshold,
+            bias: 0.05,
+            uvs: UvMapping::default(),
+        }
+    }
+
+    pub fn with_bias(mut self, bias: f32) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: UvMapping) -> Self {
+        self.uvs = uvs;
+        self
+    }
+
+    /// The triangles covering the solid part of `view`, with `sample`
+    /// reading the density and the gradient of the density of a cell.
+    pub fn triangles<'a, T: 'a, F>(
+        &self,
+        view: impl Into<ChunkView<'a, T>>,
+        cell_size: CellSize,
+        sample: F,
+    ) -> ContourMesh
+    where
+        F: Fn(&T) -> (f32, Vec2),
+    {
+        let view = view.into();
+
+        self.build(
+            view,
+            cell_size,
+            |cell| sample(cell).0,
+            |a, b, t, _| {
+                let (a, b) = (sample(a).1, sample(b).1);
+                a + (b - a) * t
+            },
+        )
+    }
+
+    /// The triangles covering the solid part of `view`, with `density`
+    /// reading the density of a cell and `normal` giving the normal of the
+    /// contour at a point in world coordinates (Hermite data).
+    pub fn hermite_triangles<'a, T: 'a, F, N>(
+        &self,
+        view: impl Into<ChunkView<'a, T>>,
+        cell_size: CellSize,
+        density: F,
+        normal: N,
+    ) -> ContourMesh
+    where
+        F: Fn(&T) -> f32,
+        N: Fn(Vec2) -> Vec2,
+    {
+        let view = view.into();
+        let chunk = view.position.to_world(view.size(), cell_size);
+
+        self.build(view, cell_size, density, |_, _, _, point| {
+            normal(chunk + point)
+        })
+    }
+
+    /// A triangle list `Mesh` covering the solid part of `view`.
+    pub fn mesh<'a, T: 'a, F>(
+        &self,
+        view: impl Into<ChunkView<'a, T>>,
+        cell_size: CellSize,
+        sample: F,
+    ) -> Mesh
+    where
+        F: Fn(&T) -> (f32, Vec2),
+    {
+        self.triangles(view, cell_size, sample).into_mesh()
+    }
+
+    /// `normal` gives the normal at a crossing `t` along the edge
+    /// between two cells, at a point relative to the center of the chunk.
+    fn build<'a, T, D, N>(
+        &self,
+        view: ChunkView<'a, T>,
+        cell_size: CellSize,
+        density: D,
+        normal: N,
+    ) -> ContourMesh
+    where
+        D: Fn(&T) -> f32,
+        N: Fn(&T, &T, f32, Vec2) -> Vec2,
+    {
+        let mut contour = ContourMesh::default();
+        let mut cache: HashMap<DualKey, u32> = HashMap::new();
+        let mut squares: HashMap<CellPosition, Option<SquareVertex>> = HashMap::new();
+
+        let size = cell_size.as_vec2();
+        let point = |cell: CellPosition| Vec2::new(cell.x as f32, cell.y as f32) * size;
+        let solid = |cell: CellPosition| view.get(cell).map(|cell| density(cell) > self.threshold);
+
+        let mut square = |cell: CellPosition| {
+            *squares
+                .entry(cell)
+                .or_insert_with(|| self.square_vertex(&view, cell, size, &density, &normal))
+        };
+
+        let mut vertex = |contour: &mut ContourMesh, key: DualKey, (point, cell): SquareVertex| {
+            *cache
+                .entry(key)
+                .or_insert_with(|| contour.push_vertex(point, cell))
+        };
+
+        let (top_left, bottom_right) = (view.size().top_left(), view.size().bottom_right());
+
+        for y in (bottom_right.y..=top_left.y).rev() {
+            for x in top_left.x..=bottom_right.x {
+                let cell = CellPosition::new(x, y);
+                let center = match square(cell) {
+                    Some(center) => center,
+                    None => continue,
+                };
+
+                // The corners counter-clockwise from the top left:
+                let corners = [
+                    cell,
+                    CellPosition::new(x, y - 1),
+                    CellPosition::new(x + 1, y - 1),
+                    CellPosition::new(x + 1, y),
+                ];
+                let solids: Vec<bool> = corners.iter().map(|&c| solid(c).unwrap()).collect();
+
+                let mut triangle =
+                    |contour: &mut ContourMesh, points: [(DualKey, SquareVertex); 3]| {
+                        let mut points = points;
+                        let position = |i: usize| {
+                            let (point, _) = points[i].1;
+                            [point.x, point.y]
+                        };
+                        let area = triangle_area([position(0), position(1), position(2)]);
+
+                        if area.abs() <= f32::EPSILON {
+                            return;
+                        }
+
+                        if area < 0.0 {
+                            points.swap(1, 2);
+                        }
+
+                        for &(key, point) in &points {
+                            let index = vertex(contour, key, point);
+                            contour.indices.push(index);
+                        }
+                    };
+
+                let corner = |i: usize| {
+                    let cell = corners[i];
+                    (DualKey::Cell(cell), (point(cell), cell))
+                };
+                let middle = (DualKey::Square(cell), center);
+
+                if solids.iter().all(|&solid| solid) {
+                    triangle(&mut contour, [corner(0), corner(1), corner(2)]);
+                    triangle(&mut contour, [corner(0), corner(2), corner(3)]);
+                } else {
+                    for i in 0..4 {
+                        if solids[i] && solids[(i + 1) % 4] {
+                            triangle(&mut contour, [corner(i), corner((i + 1) % 4), middle]);
+                        }
+                    }
+                }
+
+                // Close the gaps to the squares on the left and above, across
+                // the edges the contour crosses:
+                let sides = [((0, 1), (-1, 0)), ((3, 0), (0, 1))];
+
+                for &((a, b), (dx, dy)) in &sides {
+                    if solids[a] == solids[b] {
+                        continue;
+                    }
+
+                    let neighbour = CellPosition::new(x + dx, y + dy);
+                    let other = match square(neighbour) {
+                        Some(other) => (DualKey::Square(neighbour), other),
+                        None => continue,
+                    };
+
+                    let inside = if solids[a] { a } else { b };
+                    triangle(&mut contour, [corner(inside), middle, other]);
+                }
+            }
+        }
+
+        contour.map_uvs(self.uvs, &view, cell_size);
+        contour
+    }
+
+    /// The vertex of the square with its top left corner at `cell`, or `None`
+    /// if any of its corners is missing. Squares the contour does not cross
+    /// get their center.
+    fn square_vertex<T, D, N>(
+        &self,
+        view: &ChunkView<T>,
+        cell: CellPosition,
+        size: Vec2,
+        density: D,
+        normal: N,
+    ) -> Option<SquareVertex>
+    where
+        D: Fn(&T) -> f32,
+        N: Fn(&T, &T, f32, Vec2) -> Vec2,
+    {
+        let corners = [
+            cell,
+            CellPosition::new(cell.x + 1, cell.y),
+            CellPosition::new(cell.x + 1, cell.y - 1),
+            CellPosition::new(cell.x, cell.y - 1),
+        ];
+
+        let mut cells = Vec::with_capacity(4);
+        for &corner in &corners {
+            cells.push(view.get(corner)?);
+        }
+
+        let densities: Vec<f32> = cells.iter().map(|&cell| density(cell)).collect();
+        let point = |cell: CellPosition| Vec2::new(cell.x as f32, cell.y as f32) * size;
+
+        // The cell with the densest corner owns the vertex:
+        let densest = (0..4)
+            .max_by(|&a, &b| densities[a].partial_cmp(&densities[b]).unwrap())
+            .unwrap();
+        let owner = corners[densest];
+
+        let min = point(corners[3]);
+        let max = point(corners[1]);
+
+        // Every crossing, along with its normal:
+        let mut crossings = Vec::new();
+
+        for i in 0..4 {
+            let j = (i + 1) % 4;
+            let (a, b) = (densities[i], densities[j]);
+
+            if (a > self.threshold) == (b > self.threshold) {
+                continue;
+            }
+
+            let t = ((self.threshold - a) / (b - a)).clamp(0.0, 1.0);
+            let crossing = point(corners[i]) + (point(corners[j]) - point(corners[i])) * t;
+            crossings.push((crossing, normal(cells[i], cells[j], t, crossing)));
+        }
+
+        if crossings.is_empty() {
+            return Some(((min + max) / 2.0, owner));
+        }
+
+        let mass = crossings
+            .iter()
+            .fold(Vec2::zero(), |sum, &(crossing, _)| sum + crossing)
+            / crossings.len() as f32;
+
+        // Minimise the squared distances to the lines through every crossing
+        // along its normal, plus a pull towards the mass point. Solved around
+        // the mass point, in cells, so the bias does not depend on cell size:
+        let (mut a, mut b, mut c) = (self.bias, 0.0, self.bias);
+        let mut rhs = Vec2::zero();
+
+        for &(crossing, normal) in &crossings {
+            let normal = normal * size;
+            let length = normal.length();
+
+            if length <= f32::EPSILON {
+                continue;
+            }
+
+            let normal = normal / length;
+            let distance = normal.dot((crossing - mass) / size);
+
+            a += normal.x * normal.x;
+            b += normal.x * normal.y;
+            c += normal.y * normal.y;
+            rhs += normal * distance;
+        }
+
+        let determinant = a * c - b * b;
+        let offset = if determinant.abs() <= f32::EPSILON {
+            Vec2::zero()
+        } else {
+            Vec2::new(c * rhs.x - b * rhs.y, a * rhs.y - b * rhs.x) / determinant
+        };
+
+        let vertex = mass + offset * size;
+        let vertex = Vec2::new(vertex.x.clamp(min.x, max.x), vertex.y.clamp(min.y, max.y));
+
+        Some((vertex, owner))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{ChunkData, ChunkPosition, ChunkSize, MarchingSquares};
+
+    #[test]
+    fn solid_chunks() {
+        let size = ChunkSize::new(4, 4);
+        let solid = ChunkData::new_with(size, 1.0f32);
+        let dual = DualContouring::new(0.5);
+        let sample = |&density: &f32| (density, Vec2::zero());
+
+        let alone = dual.triangles(&solid, CellSize::new(2, 2), sample);
+        assert!((alone.area() - 9.0 * 4.0).abs() < 1e-4);
+
+        let view = ChunkView::with_neighbours(&solid, |_| Some(&solid));
+        let full = dual.triangles(view, CellSize::new(2, 2), sample);
+        assert!((full.area() - 16.0 * 4.0).abs() < 1e-4);
+
+        let empty = ChunkData::new_with(size, 0.0f32);
+        assert!(dual
+            .triangles(&empty, CellSize::new(2, 2), sample)
+            .is_empty());
+    }
+
+    #[test]
+    fn sharp_corners() {
+        // A solid quadrant with its corner inside a square:
+        let corner = Vec2::new(1.4, 1.5);
+        let data = ChunkData::new_with_seed(ChunkSize::new(6, 6), |pos| {
+            (corner.x - pos.x as f32).min(corner.y - pos.y as f32)
+        });
+        let normal = |point: Vec2| {
+            if corner.x - point.x < corner.y - point.y {
+                Vec2::new(1.0, 0.0)
+            } else {
+                Vec2::new(0.0, 1.0)
+            }
+        };
+
+        let size = CellSize::new(1, 1);
+        let closest = |contour: &ContourMesh| {
+            contour
+                .positions
+                .iter()
+                .map(|&[x, y]| (Vec2::new(x, y) - corner).length())
+                .fold(f32::MAX, f32::min)
+        };
+
+        let dual = DualContouring::new(0.0).hermite_triangles(&data, size, |&d| d, normal);
+        let squares = MarchingSquares::new(0.0).triangles(&data, size, |&d| d);
+
+        assert!(closest(&dual) < 0.1);
+        assert!(closest(&squares) > 0.3);
+
+        for tri in dual.triangles() {
+            assert!(triangle_area(tri) > 0.0);
+        }
+    }
+
+    #[test]
+    fn neighbours_share_vertices() {
+        let chunk_size = ChunkSize::new(4, 4);
+        let cell_size = CellSize::new(1, 1);
+        let width = 4.0;
+
+        // A wavy slope across both chunks:
+        let chunk = |position: i32| {
+            ChunkData::new_with_seed(chunk_size, |pos| {
+                let x = pos.x as f32 + position as f32 * width;
+                let y = pos.y as f32;
+                (
+                    0.3 * x + (x * 0.7).sin() - y,
+                    Vec2::new(0.3 + 0.7 * (x * 0.7).cos(), -1.0),
+                )
+            })
+        };
+        let (left, right) = (chunk(0), chunk(1));
+
+        let dual = DualContouring::new(0.0);
+        let mut left_view = ChunkView::new(&left);
+        left_view.set_neighbour((1, 0), Some(&right));
+        let mut right_view = ChunkView::new(&right).at(ChunkPosition::new(1, 0));
+        right_view.set_neighbour((-1, 0), Some(&left));
+
+        let left = dual.triangles(left_view, cell_size, |&cell| cell);
+        let right = dual.triangles(right_view, cell_size, |&cell| cell);
+        assert!(!left.is_empty() && !right.is_empty());
+
+        // The vertices the right chunk borrows from the squares of the left
+        // chunk, whose last column of squares ends at the right chunk's cells:
+        let borrowed: Vec<Vec2> = right
+            .positions
+            .iter()
+            .map(|&[x, y]| Vec2::new(x + width, y))
+            .filter(|point| point.x < 2.0 - 1e-3)
+            .collect();
+        assert!(!borrowed.is_empty());
+
+        for point in borrowed {
+            assert!(left
+                .positions
+                .iter()
+                .any(|&[x, y]| (Vec2::new(x, y) - point).length() < 1e-5));
+        }
+    }
+}
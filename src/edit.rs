@@ -0,0 +1,319 @@
+use bevy::prelude::*;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::{
+    dependent_chunks, CellPosition, CellRect, Chunk, ChunkData, ChunkInfo, ChunkLayer, ChunkMap,
+    ChunkMesher, ChunkPosition, ChunkQueues, FloatingOrigin, GlobalCellPosition,
+};
+
+/// Where an edit is made, either a point in the same frame as
+/// `Transform`s or the global position of a cell.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EditPosition {
+    World(Vec2),
+    Cell(GlobalCellPosition),
+}
+
+impl EditPosition {
+    /// The global position of the cell that is edited.
+    pub fn to_cell<L>(self, info: &ChunkInfo<L>, origin: &FloatingOrigin) -> GlobalCellPosition {
+        match self {
+            EditPosition::World(local) => {
+                let cell = GlobalCellPosition::from_world(info.cell_size, local);
+                let shift = GlobalCellPosition::from_chunk(
+                    info.chunk_size,
+                    origin.origin,
+                    CellPosition::new(0, 0),
+                );
+
+                GlobalCellPosition::new(cell.x + shift.x, cell.y + shift.y)
+            }
+            EditPosition::Cell(cell) => cell,
+        }
+    }
+}
+
+impl From<Vec2> for EditPosition {
+    fn from(world: Vec2) -> Self {
+        EditPosition::World(world)
+    }
+}
+
+impl From<GlobalCellPosition> for EditPosition {
+    fn from(cell: GlobalCellPosition) -> Self {
+        EditPosition::Cell(cell)
+    }
+}
+
+/// Modifies a cell given its distance from the center of
+/// the brush, from 0.0 at the center to 1.0 at the edge.
+pub struct Brush<T>(pub Arc<BrushFn<T>>);
+
+type BrushFn<T> = dyn Fn(&mut T, f32) + Send + Sync;
+
+impl<T> Brush<T> {
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(&mut T, f32) + Send + Sync + 'static,
+    {
+        Self(Arc::new(f))
+    }
+}
+
+impl<T> Clone for Brush<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+/// An edit to the cells of the layer `L`, sent as an event.
+///
+/// Edits are applied in the `CHUNK_EDIT` stage, and the chunks they
+/// change are remeshed (rebuilding their colliders) right after. Edits
+/// on the border of a chunk also remesh the neighbours whose meshes
+/// sample the edited cells. Edits to chunks that are not loaded are dropped.
+pub enum CellEdit<L: ChunkLayer> {
+    /// Replace a single cell.
+    SetCell {
+        position: EditPosition,
+        value: L::Cell,
+    },
+    /// Replace every cell between two corners, inclusive.
+    FillRect {
+        min: EditPosition,
+        max: EditPosition,
+        value: L::Cell,
+    },
+    /// Apply a brush to every cell at most `radius` cells from `center`.
+    ApplyBrush {
+        center: EditPosition,
+        radius: f32,
+        brush: Brush<L::Cell>,
+    },
+}
+
+impl<L: ChunkLayer> CellEdit<L> {
+    pub fn set(position: impl Into<EditPosition>, value: L::Cell) -> Self {
+        CellEdit::SetCell {
+            position: position.into(),
+            value,
+        }
+    }
+
+    /// Fill the rectangle between two opposite corners.
+    pub fn fill_rect(
+        corner: impl Into<EditPosition>,
+        opposite: impl Into<EditPosition>,
+        value: L::Cell,
+    ) -> Self {
+        CellEdit::FillRect {
+            min: corner.into(),
+            max: opposite.into(),
+            value,
+        }
+    }
+
+    pub fn brush<F>(center: impl Into<EditPosition>, radius: f32, brush: F) -> Self
+    where
+        F: Fn(&mut L::Cell, f32) + Send + Sync + 'static,
+    {
+        CellEdit::ApplyBrush {
+            center: center.into(),
+            radius,
+            brush: Brush::new(brush),
+        }
+    }
+
+    /// The cells covered by the edit grouped by chunk, along with
+    /// their distance from the center of the brush.
+    pub fn cells(
+        &self,
+        info: &ChunkInfo<L>,
+        origin: &FloatingOrigin,
+    ) -> HashMap<ChunkPosition, Vec<(CellPosition, f32)>> {
+        let mut cells: HashMap<ChunkPosition, Vec<(CellPosition, f32)>> = HashMap::new();
+        let mut push = |cell: GlobalCellPosition, distance: f32| {
+            let (chunk, cell) = cell.to_chunk(info.chunk_size);
+            cells.entry(chunk).or_default().push((cell, distance));
+        };
+
+        match self {
+            CellEdit::SetCell { position, .. } => push(position.to_cell(info, origin), 0.0),
+            CellEdit::FillRect { min, max, .. } => {
+                let (a, b) = (min.to_cell(info, origin), max.to_cell(info, origin));
+
+                for y in a.y.min(b.y)..=a.y.max(b.y) {
+                    for x in a.x.min(b.x)..=a.x.max(b.x) {
+                        push(GlobalCellPosition::new(x, y), 0.0);
+                    }
+                }
+            }
+            CellEdit::ApplyBrush { center, radius, .. } => {
+                let center = center.to_cell(info, origin);
+                let reach = radius.max(0.0).ceil() as i32;
+
+                for y in center.y - reach..=center.y + reach {
+                    for x in center.x - reach..=center.x + reach {
+                        let delta = Vec2::new((x - center.x) as f32, (y - center.y) as f32);
+                        let distance = delta.length();
+
+                        if distance <= *radius {
+                            let distance = if *radius > 0.0 {
+                                distance / radius
+                            } else {
+                                0.0
+                            };
+
+                            push(GlobalCellPosition::new(x, y), distance);
+                        }
+                    }
+                }
+            }
+        }
+
+        cells
+    }
+
+    /// Apply the edit to `cells` of a single chunk, as given by `cells()`.
+    pub fn apply(&self, data: &mut ChunkData<L::Cell>, cells: &[(CellPosition, f32)]) {
+        for &(pos, distance) in cells {
+            let cell = match data.get_mut(pos) {
+                Some(cell) => cell,
+                None => continue,
+            };
+
+            match self {
+                CellEdit::SetCell { value, .. } | CellEdit::FillRect { value, .. } => {
+                    *cell = value.clone()
+                }
+                CellEdit::ApplyBrush { brush, .. } => (brush.0)(cell, distance),
+            }
+        }
+    }
+}
+
+/// Apply every `CellEdit` sent since the last frame and queue the
+/// chunks they changed, and their dependent neighbours, for remeshing.
+pub fn apply_cell_edits<L: ChunkLayer>(
+    info: Res<ChunkInfo<L>>,
+    origin: Res<FloatingOrigin>,
+    map: Res<ChunkMap<L>>,
+    mesher: Res<ChunkMesher<L>>,
+    mut queues: ResMut<ChunkQueues<L>>,
+    mut reader: Local<EventReader<CellEdit<L>>>,
+    edits: Res<Events<CellEdit<L>>>,
+    mut chunks: Query<&mut Chunk<L::Cell>, With<L>>,
+) {
+    let mut dependents: Vec<(ChunkPosition, CellRect)> = Vec::new();
+
+    for edit in reader.iter(&edits) {
+        for (position, cells) in edit.cells(&info, &origin) {
+            let entity = match map.0.get(&position) {
+                Some(&entity) => entity,
+                None => continue,
+            };
+
+            if let Ok(mut chunk) = chunks.get_mut(entity) {
+                edit.apply(&mut chunk.data, &cells);
+
+                // Edited chunks are remeshed before chunks that just loaded:
+                queues.mesh.push(position, 0.0);
+
+                for &(cell, _) in &cells {
+                    for (offset, rect) in
+                        dependent_chunks(info.chunk_size, cell, mesher.neighbours())
+                    {
+                        dependents.push((position + offset, rect));
+                    }
+                }
+            }
+        }
+    }
+
+    for (position, rect) in dependents {
+        let entity = match map.0.get(&position) {
+            Some(&entity) => entity,
+            None => continue,
+        };
+
+        if let Ok(mut chunk) = chunks.get_mut(entity) {
+            chunk.data.mark_dirty(rect);
+            queues.mesh.push(position, 0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{CellSize, ChunkSize};
+
+    #[derive(Default)]
+    struct Terrain;
+
+    impl ChunkLayer for Terrain {
+        type Cell = u8;
+    }
+
+    fn info() -> ChunkInfo<Terrain> {
+        ChunkInfo::new(ChunkSize::new(4, 4), CellSize::new(2, 2))
+    }
+
+    #[test]
+    fn edits_across_chunks() {
+        let info = info();
+        let origin = FloatingOrigin::fixed();
+
+        let fill = CellEdit::<Terrain>::fill_rect(
+            GlobalCellPosition::new(1, 0),
+            GlobalCellPosition::new(2, -1),
+            1,
+        );
+        let cells = fill.cells(&info, &origin);
+
+        assert_eq!(2, cells.len());
+        assert_eq!(2, cells[&ChunkPosition::new(0, 0)].len());
+        assert_eq!(2, cells[&ChunkPosition::new(1, 0)].len());
+
+        let mut data = ChunkData::new_with(info.chunk_size, 0);
+        fill.apply(&mut data, &cells[&ChunkPosition::new(1, 0)]);
+        assert_eq!(Some(&1), data.get((-2, -1)));
+        assert_eq!(2, data.iter().filter(|&&cell| cell == 1).count());
+    }
+
+    #[test]
+    fn world_positions_follow_origin() {
+        let info = info();
+        let mut origin = FloatingOrigin::new(1);
+        origin.origin = ChunkPosition::new(10, 0);
+
+        let set = CellEdit::<Terrain>::set(Vec2::new(2.2, -1.9), 1);
+        let cells = set.cells(&info, &origin);
+
+        assert_eq!(
+            vec![(CellPosition::new(1, -1), 0.0)],
+            cells[&ChunkPosition::new(10, 0)]
+        );
+    }
+
+    #[test]
+    fn brush_distances() {
+        let info = info();
+        let origin = FloatingOrigin::fixed();
+
+        let brush = CellEdit::<Terrain>::brush(GlobalCellPosition::new(0, 0), 1.0, |cell, d| {
+            *cell = if d == 0.0 { 2 } else { 1 }
+        });
+        let cells = brush.cells(&info, &origin);
+        assert_eq!(5, cells[&ChunkPosition::new(0, 0)].len());
+
+        let mut data = ChunkData::new_with(info.chunk_size, 0);
+        brush.apply(&mut data, &cells[&ChunkPosition::new(0, 0)]);
+        assert_eq!(Some(&2), data.get((0, 0)));
+        assert_eq!(Some(&1), data.get((0, 1)));
+        assert_eq!(Some(&0), data.get((1, 1)));
+    }
+}
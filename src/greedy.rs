@@ -0,0 +1,380 @@
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::pipeline::PrimitiveTopology;
+
+use crate::{CellPosition, CellRect, CellSize, ChunkData, SurfaceMesh};
+
+/// A rectangle of cells with the same key, merged by `greedy_rects`.
+/// `x` goes right and `y` goes down from the first cell of the grid.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct GreedyRect<K> {
+    pub key: K,
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Merge neighbouring cells of a `width` by `height` grid that have the
+/// same key into rectangles, as wide as they can be and then as tall as
+/// they can be. Cells without a key are left out.
+///
+/// 3D voxels are meshed by merging the faces of every slice of the
+/// chunk along each axis, one grid per slice, see `greedy_faces`.
+pub fn greedy_rects<K, F>(width: usize, height: usize, mut key: F) -> Vec<GreedyRect<K>>
+where
+    K: PartialEq,
+    F: FnMut(usize, usize) -> Option<K>,
+{
+    let mut keys: Vec<Option<K>> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| key(x, y))
+        .collect();
+
+    let mut rects = Vec::new();
+
+    for y in 0..height {
+        for x in 0..width {
+            let key = match keys[y * width + x].take() {
+                Some(key) => key,
+                None => continue,
+            };
+
+            let same =
+                |keys: &[Option<K>], x: usize, y: usize| keys[y * width + x].as_ref() == Some(&key);
+
+            let mut w = 1;
+            while x + w < width && same(&keys, x + w, y) {
+                w += 1;
+            }
+
+            let mut h = 1;
+            while y + h < height && (x..x + w).all(|x| same(&keys, x, y + h)) {
+                h += 1;
+            }
+
+            // Every cell of the rectangle is merged, so it is left out of later rectangles:
+            for row in y..y + h {
+                for column in x..x + w {
+                    keys[row * width + column] = None;
+                }
+            }
+
+            rects.push(GreedyRect {
+                key,
+                x,
+                y,
+                width: w,
+                height: h,
+            });
+        }
+    }
+
+    rects
+}
+
+impl<T> ChunkData<T> {
+    /// The cells of the chunk with the same key merged into rectangles,
+    /// see `greedy_rects`.
+    pub fn greedy_rects<K, F>(&self, key: F) -> Vec<(K, CellRect)>
+    where
+        K: PartialEq,
+        F: Fn(&T) -> Option<K>,
+    {
+        let (width, height) = (self.size.width, self.size.height);
+        let top_left = self.size.top_left();
+        let cell =
+            |x: usize, y: usize| CellPosition::new(top_left.x + x as i32, top_left.y - y as i32);
+
+        greedy_rects(width, height, |x, y| key(&self.data[y * width + x]))
+            .into_iter()
+            .map(|rect| {
+                let min = cell(rect.x, rect.y);
+                let max = cell(rect.x + rect.width - 1, rect.y + rect.height - 1);
+
+                (rect.key, CellRect::new(min, max))
+            })
+            .collect()
+    }
+}
+
+/// A mesh with a quad for every rectangle of cells with the same key,
+/// relative to the center of the chunk. Texture coordinates count cells,
+/// so a repeating texture covers every cell once.
+pub fn greedy_mesh<T, K, F>(data: &ChunkData<T>, cell_size: CellSize, key: F) -> Mesh
+where
+    K: PartialEq,
+    F: Fn(&T) -> Option<K>,
+{
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut indices = Vec::new();
+
+    let size = cell_size.as_vec2();
+
+    for (_, rect) in data.greedy_rects(key) {
+        let min = Vec2::new(rect.min.x as f32, rect.min.y as f32) * size - size / 2.0;
+        let max = Vec2::new(rect.max.x as f32, rect.max.y as f32) * size + size / 2.0;
+        let (width, height) = (rect.width() as f32, rect.height() as f32);
+        let start = positions.len() as u32;
+
+        // Counter-clockwise from the bottom left:
+        positions.extend_from_slice(&[
+            [min.x, min.y, 0.0],
+            [max.x, min.y, 0.0],
+            [max.x, max.y, 0.0],
+            [min.x, max.y, 0.0],
+        ]);
+        uvs.extend_from_slice(&[[0.0, height], [width, height], [width, 0.0], [0.0, 0.0]]);
+        indices.extend_from_slice(&[start, start + 1, start + 2, start, start + 2, start + 3]);
+    }
+
+    let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+
+    mesh
+}
+
+/// A face of a 3D chunk between cells with the same key and empty
+/// cells, merged by `greedy_faces`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct GreedyFace<K> {
+    pub key: K,
+    /// The axis the face is facing along, 0 for x, 1 for y and 2 for z.
+    pub axis: usize,
+    /// Whether the face is facing towards the end of `axis`.
+    pub positive: bool,
+    /// The corner of the face nearest the first cell of the chunk.
+    pub min: [i32; 3],
+    /// The opposite corner, the same as `min` along `axis`.
+    pub max: [i32; 3],
+}
+
+/// Merge the faces between solid and empty cells of a 3D chunk of `size`
+/// cells along x, y and z into rectangles, with `key` reading the key
+/// of a cell and returning `None` for empty cells. The cell at `[x, y, z]`
+/// is a box from `[x, y, z]` to `[x + 1, y + 1, z + 1]` times the cell size.
+///
+/// Faces along the sides of the chunk need the cells of the neighbouring
+/// chunks, so `key` should read them one before and one past the end of
+/// the chunk. Faces next to neighbours that are not loaded are kept.
+pub fn greedy_faces<K, F>(size: [usize; 3], key: F) -> Vec<GreedyFace<K>>
+where
+    K: PartialEq,
+    F: Fn([i32; 3]) -> Option<K>,
+{
+    let mut faces = Vec::new();
+
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        for &positive in &[false, true] {
+            for slice in 0..size[axis] as i32 {
+                let cell = |a: usize, b: usize| {
+                    let mut pos = [slice; 3];
+                    pos[u] = a as i32;
+                    pos[v] = b as i32;
+                    pos
+                };
+
+                // Every slice is a grid of the faces of its cells on one side:
+                let rects = greedy_rects(size[u], size[v], |a, b| {
+                    let pos = cell(a, b);
+                    let mut next = pos;
+                    next[axis] += if positive { 1 } else { -1 };
+
+                    match key(next) {
+                        Some(_) => None,
+                        None => key(pos),
+                    }
+                });
+
+                for rect in rects {
+                    let mut min = cell(rect.x, rect.y);
+                    let mut max = cell(rect.x + rect.width, rect.y + rect.height);
+                    min[axis] += positive as i32;
+                    max[axis] = min[axis];
+
+                    faces.push(GreedyFace {
+                        key: rect.key,
+                        axis,
+                        positive,
+                        min,
+                        max,
+                    });
+                }
+            }
+        }
+    }
+
+    faces
+}
+
+/// The surface of the solid cells of a 3D chunk, with a quad for every
+/// face merged by `greedy_faces`, relative to the first cell of the chunk.
+pub fn greedy_surface<K, F>(size: [usize; 3], cell_size: Vec3, key: F) -> SurfaceMesh
+where
+    K: PartialEq,
+    F: Fn([i32; 3]) -> Option<K>,
+{
+    let mut surface = SurfaceMesh::default();
+    let scale = [cell_size.x, cell_size.y, cell_size.z];
+
+    for face in greedy_faces(size, key) {
+        let (u, v) = ((face.axis + 1) % 3, (face.axis + 2) % 3);
+        let point = |a: i32, b: i32| {
+            let mut pos = face.min;
+            pos[u] = a;
+            pos[v] = b;
+            [
+                pos[0] as f32 * scale[0],
+                pos[1] as f32 * scale[1],
+                pos[2] as f32 * scale[2],
+            ]
+        };
+
+        // Counter-clockwise seen from the end of the axis, as u by v is along it:
+        let mut corners = [
+            point(face.min[u], face.min[v]),
+            point(face.max[u], face.min[v]),
+            point(face.max[u], face.max[v]),
+            point(face.min[u], face.max[v]),
+        ];
+        if !face.positive {
+            corners.reverse();
+        }
+
+        let mut normal = [0.0; 3];
+        normal[face.axis] = if face.positive { 1.0 } else { -1.0 };
+
+        let start = surface.positions.len() as u32;
+        surface.positions.extend_from_slice(&corners);
+        surface.normals.extend_from_slice(&[normal; 4]);
+        surface.indices.extend_from_slice(&[
+            start,
+            start + 1,
+            start + 2,
+            start,
+            start + 2,
+            start + 3,
+        ]);
+    }
+
+    surface
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ChunkSize;
+
+    #[test]
+    fn maximal_rects() {
+        // A 3 by 2 block of 1s, an L of 2s and a hole:
+        #[rustfmt::skip]
+        let keys = [
+            1, 1, 1, 2,
+            1, 1, 1, 2,
+            2, 2, 0, 2,
+        ];
+
+        let rects = greedy_rects(4, 3, |x, y| Some(keys[y * 4 + x]).filter(|&key| key != 0));
+
+        assert_eq!(
+            vec![
+                GreedyRect {
+                    key: 1,
+                    x: 0,
+                    y: 0,
+                    width: 3,
+                    height: 2
+                },
+                GreedyRect {
+                    key: 2,
+                    x: 3,
+                    y: 0,
+                    width: 1,
+                    height: 3
+                },
+                GreedyRect {
+                    key: 2,
+                    x: 0,
+                    y: 2,
+                    width: 2,
+                    height: 1
+                },
+            ],
+            rects
+        );
+
+        let checkers = greedy_rects(4, 4, |x, y| Some((x + y) % 2));
+        assert_eq!(16, checkers.len());
+    }
+
+    #[test]
+    fn merged_chunk_mesh() {
+        let size = ChunkSize::new(8, 8);
+        let data = ChunkData::new_with_seed(size, |pos| pos.y < 2);
+
+        let rects = data.greedy_rects(|&solid| if solid { Some(()) } else { None });
+        assert_eq!(
+            vec![((), CellRect::new((-4, 1).into(), (3, -3).into()))],
+            rects
+        );
+
+        let mesh = greedy_mesh(&data, CellSize::new(2, 2), |&solid| {
+            if solid {
+                Some(())
+            } else {
+                None
+            }
+        });
+        assert_eq!(4, mesh.count_vertices());
+    }
+
+    #[test]
+    fn merged_voxel_faces() {
+        // A 2 by 2 by 2 cube of 1s in a chunk of 4 cells along every axis,
+        // with a bar of 2s along x next to it:
+        let inside = |[x, y, z]: [i32; 3]| [x, y, z].iter().all(|&v| (0..4).contains(&v));
+        let key = |[x, y, z]: [i32; 3]| match [x, y, z] {
+            _ if !inside([x, y, z]) => None,
+            [1..=2, 1..=2, 1..=2] => Some(1),
+            [_, 0, 0] => Some(2),
+            _ => None,
+        };
+
+        let faces = greedy_faces([4, 4, 4], key);
+        let count = |k: i32| faces.iter().filter(|face| face.key == k).count();
+
+        // One face per side of the cube and of the bar,
+        // which only touches the cube along an edge:
+        assert_eq!(6, count(1));
+        assert_eq!(6, count(2));
+
+        let surface = greedy_surface([4, 4, 4], Vec3::new(1.0, 2.0, 1.0), key);
+        assert_eq!(12 * 6, surface.indices.len());
+
+        // Every triangle faces along its normal, away from the solid cells:
+        let mut area = 0.0;
+        for (i, [a, b, c]) in surface.triangles().enumerate() {
+            let (a, b, c) = (Vec3::from(a), Vec3::from(b), Vec3::from(c));
+            let cross = (b - a).cross(c - a);
+            let normal = Vec3::from(surface.normals[surface.indices[i * 3] as usize]);
+
+            assert!(cross.dot(normal) > 0.0);
+            area += cross.length() / 2.0;
+        }
+
+        // The cube is 2 by 4 by 2 and the bar 4 by 2 by 1:
+        let cube = 2.0 * (2.0 * 4.0 + 4.0 * 2.0 + 2.0 * 2.0);
+        let bar = 2.0 * (4.0 * 2.0 + 2.0 * 1.0 + 4.0 * 1.0);
+        assert!((area - cube - bar).abs() < 1e-4);
+    }
+}
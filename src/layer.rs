@@ -0,0 +1,95 @@
+use bevy::ecs::Component;
+
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+
+use crate::{CellSize, ChunkSize};
+
+/// A set of chunks with its own cell type, sizes, generator and mesher.
+///
+/// Every layer shares the same chunk coordinates, so several layers
+/// (terrain, background walls, fluids...) can be loaded side by side.
+/// The layer type is added as a marker component to its chunk entities.
+pub trait ChunkLayer: Component + Default {
+    type Cell: Clone + Send + Sync + 'static;
+}
+
+/// The sizes of the chunks of the layer `L`.
+pub struct ChunkInfo<L> {
+    pub chunk_size: ChunkSize,
+    pub cell_size: CellSize,
+    marker: PhantomData<fn() -> L>,
+}
+
+impl<L> ChunkInfo<L> {
+    pub fn new(chunk_size: ChunkSize, cell_size: CellSize) -> Self {
+        Self {
+            chunk_size,
+            cell_size,
+            marker: PhantomData,
+        }
+    }
+
+    /// The grid of chunk coordinates this layer is on.
+    pub fn grid(&self) -> ChunkGrid {
+        ChunkGrid {
+            chunk_size: self.chunk_size,
+            cell_size: self.cell_size,
+        }
+    }
+}
+
+impl<L> Default for ChunkInfo<L> {
+    fn default() -> Self {
+        ChunkGrid::default().layer()
+    }
+}
+
+impl<L> Clone for ChunkInfo<L> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<L> Copy for ChunkInfo<L> {}
+
+impl<L> Debug for ChunkInfo<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChunkInfo")
+            .field("chunk_size", &self.chunk_size)
+            .field("cell_size", &self.cell_size)
+            .finish()
+    }
+}
+
+/// The chunk coordinates shared by every layer. Loaders, tickets,
+/// tracked entities and the floating origin all use this grid.
+///
+/// Layers can have different chunk and cell sizes, but their chunks
+/// must cover the same area of the world.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ChunkGrid {
+    pub chunk_size: ChunkSize,
+    pub cell_size: CellSize,
+}
+
+impl ChunkGrid {
+    /// Chunk sizes of a layer on this grid.
+    pub fn layer<L>(&self) -> ChunkInfo<L> {
+        ChunkInfo::new(self.chunk_size, self.cell_size)
+    }
+
+    /// Whether chunks of `other` cover the same area as chunks of `self`.
+    pub fn is_compatible(&self, other: ChunkGrid) -> bool {
+        self.chunk_size.world_size(self.cell_size) == other.chunk_size.world_size(other.cell_size)
+    }
+}
+
+impl Default for ChunkGrid {
+    fn default() -> Self {
+        Self {
+            chunk_size: ChunkSize::new(64, 64),
+            cell_size: CellSize::new(8, 8),
+        }
+    }
+}
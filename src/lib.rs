@@ -1,9 +1,51 @@
 pub mod chunk;
 pub mod chunk_data;
+pub mod collider;
+pub mod contour;
+pub mod dual_contouring;
+pub mod edit;
+pub mod greedy;
+pub mod layer;
+pub mod loader;
+pub mod lod;
+pub mod marching_cubes;
+pub mod marching_squares;
+pub mod mesh;
+pub mod mip;
+pub mod origin;
+pub mod owned;
+pub mod plugin;
 pub mod position;
+pub mod queue;
 pub mod size;
+pub mod spatial;
+pub mod ticket;
+pub mod tilemap;
+pub mod tracking;
+pub mod view;
 
 pub use chunk::*;
 pub use chunk_data::*;
+pub use collider::*;
+pub use contour::*;
+pub use dual_contouring::*;
+pub use edit::*;
+pub use greedy::*;
+pub use layer::*;
+pub use loader::*;
+pub use lod::*;
+pub use marching_cubes::*;
+pub use marching_squares::*;
+pub use mesh::*;
+pub use mip::*;
+pub use origin::*;
+pub use owned::*;
+pub use plugin::*;
 pub use position::*;
+pub use queue::*;
 pub use size::*;
+pub use spatial::*;
+pub use ticket::*;
+pub use tilemap::*;
+pub use tracking::*;
+pub use view::*;
@@ -0,0 +1,57 @@
+use bevy::prelude::Vec2;
+
+/// Keeps every chunk within `radius` chunks of the entity loaded.
+///
+/// The entity also needs a `ChunkPosition` component, add
+/// `ChunkTracked` to keep it up to date as the entity moves.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChunkLoader {
+    pub radius: u32,
+    /// How strongly chunks in the direction of `ChunkLoaderVelocity`
+    /// are preferred, from `0.0` (not at all) to `1.0`.
+    pub velocity_bias: f32,
+}
+
+impl ChunkLoader {
+    pub fn new(radius: u32) -> Self {
+        Self {
+            radius,
+            velocity_bias: 0.0,
+        }
+    }
+
+    pub fn with_velocity_bias(mut self, velocity_bias: f32) -> Self {
+        self.velocity_bias = velocity_bias;
+        self
+    }
+
+    /// The load priority of the chunk centered on `chunk_world`,
+    /// lower values are loaded first.
+    ///
+    /// This is the distance from the loader to the chunk, shrunk for
+    /// chunks ahead of a moving loader and grown for the ones behind it.
+    pub fn priority(&self, loader_world: Vec2, velocity: Option<Vec2>, chunk_world: Vec2) -> f32 {
+        let offset = chunk_world - loader_world;
+        let distance = offset.length();
+
+        match velocity {
+            Some(velocity) if distance > 0.0 && velocity.length_squared() > 0.0 => {
+                let alignment = offset.normalize().dot(velocity.normalize());
+
+                distance * (1.0 - self.velocity_bias * alignment)
+            }
+            _ => distance,
+        }
+    }
+}
+
+impl Default for ChunkLoader {
+    fn default() -> Self {
+        ChunkLoader::new(1)
+    }
+}
+
+/// The velocity of a `ChunkLoader` in world units per second.
+/// Optional, used to load the chunks a loader is heading towards first.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct ChunkLoaderVelocity(pub Vec2);
@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+
+use crate::{
+    CellPosition, CellSize, ChunkData, ChunkInfo, ChunkLayer, ChunkMips, ChunkPosition, ChunkSize,
+    ChunkView,
+};
+
+/// The levels of detail of the chunks of the layer `L`.
+///
+/// Chunks further than `distances[i]` from the nearest `ChunkLoader` are
+/// meshed at level `i + 1`, keeping one cell out of every `2^level` along
+/// each axis, with cells `2^level` times as large. Levels are limited so
+/// every level still has an even number of cells along both axes. Without
+/// any distances (the default) every chunk is meshed with all of its cells.
+///
+/// A chunk only changes level once it is `hysteresis` past a distance,
+/// so chunks near a distance do not keep swapping meshes, and the old
+/// mesh of a chunk stays until its new mesh is uploaded.
+///
+/// The cells along the border of a chunk and a coarser neighbour are
+/// blended between the cells of the coarser chunk with `lerp`, so both
+/// meshes cross that border at the same points without cracks.
+///
+/// The coarser cells of every chunk are cached, and only
+/// updated where the chunk changed when it is remeshed.
+pub struct ChunkLods<L: ChunkLayer> {
+    distances: Vec<f32>,
+    pub hysteresis: f32,
+    lerp: Option<CellLerp<L::Cell>>,
+    levels: HashMap<ChunkPosition, u8>,
+    mips: HashMap<ChunkPosition, ChunkMips<L::Cell, L::Cell>>,
+}
+
+/// Blends two cells, `t` of the way from the first to the second.
+pub type CellLerp<T> = Box<dyn Fn(&T, &T, f32) -> T + Send + Sync>;
+
+impl<L: ChunkLayer> ChunkLods<L> {
+    /// Levels of detail starting at each of `distances`, in world units,
+    /// with `lerp` blending the borders between levels.
+    pub fn new<F>(distances: Vec<f32>, lerp: F) -> Self
+    where
+        F: Fn(&L::Cell, &L::Cell, f32) -> L::Cell + Send + Sync + 'static,
+    {
+        Self {
+            distances,
+            hysteresis: 0.0,
+            lerp: Some(Box::new(lerp)),
+            levels: HashMap::new(),
+            mips: HashMap::new(),
+        }
+    }
+
+    pub fn with_hysteresis(mut self, hysteresis: f32) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    pub fn distances(&self) -> &[f32] {
+        &self.distances
+    }
+
+    /// The current level of the chunk at `position`.
+    pub fn level(&self, position: ChunkPosition) -> u8 {
+        self.levels.get(&position).copied().unwrap_or(0)
+    }
+
+    /// The level of a chunk at `distance` from the nearest loader,
+    /// currently at level `current`.
+    pub fn select(&self, current: u8, distance: f32) -> u8 {
+        let mut level = (current as usize).min(self.distances.len());
+
+        while level < self.distances.len() && distance > self.distances[level] + self.hysteresis {
+            level += 1;
+        }
+
+        while level > 0 && distance < self.distances[level - 1] - self.hysteresis {
+            level -= 1;
+        }
+
+        level as u8
+    }
+
+    /// Move the chunk at `position` to the level for `distance`, limited to
+    /// the levels chunks of `chunk_size` have. Returns whether it changed.
+    pub fn update(
+        &mut self,
+        position: ChunkPosition,
+        chunk_size: ChunkSize,
+        distance: f32,
+    ) -> bool {
+        let current = self.level(position);
+        let level = self.select(current, distance).min(max_level(chunk_size));
+
+        if level == 0 {
+            self.levels.remove(&position);
+        } else {
+            self.levels.insert(position, level);
+        }
+
+        level != current
+    }
+
+    /// Forget the levels of the chunks `keep` returns false for.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(ChunkPosition) -> bool,
+    {
+        self.levels.retain(|&position, _| keep(position));
+        self.mips.retain(|&position, _| keep(position));
+    }
+
+    /// The cells of `view` and its neighbours at the level of its chunk,
+    /// with the borders to coarser neighbours blended. Returns `None` if
+    /// the chunk and its neighbours all have every cell, so `view` can be
+    /// meshed as it is.
+    pub fn cells<'a>(&'a mut self, view: &ChunkView<'a, L::Cell>) -> Option<LodCells<'a, L::Cell>> {
+        let level = self.level(view.position);
+        let sides = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+
+        // The borders to the left and above only need the cells of the chunk,
+        // so they are blended whether or not the mesher samples those chunks.
+        // Chunks that are not loaded have no level, so are never coarser:
+        let coarser: Vec<((i32, i32), u8)> = sides
+            .iter()
+            .map(|&offset| (offset, self.level(view.position + offset.into())))
+            .filter(|&(_, neighbour)| neighbour > level)
+            .collect();
+
+        if level == 0 && coarser.is_empty() {
+            return None;
+        }
+
+        // Bring the cached levels up to date before borrowing them:
+        if level > 0 {
+            let neighbours = ChunkView::<L::Cell>::NEIGHBOURS
+                .iter()
+                .zip(&view.neighbours)
+                .filter_map(|(&offset, data)| Some((view.position + offset.into(), (*data)?)));
+
+            for (position, data) in std::iter::once((view.position, view.data)).chain(neighbours) {
+                self.mips
+                    .entry(position)
+                    .or_insert_with(|| ChunkMips::new(|cells: &[&L::Cell]| cells[0].clone()))
+                    .level(data, level);
+            }
+        }
+
+        let lods: &'a Self = self;
+        let decimated = |position: ChunkPosition, data: &'a ChunkData<L::Cell>| match level {
+            0 => data,
+            _ => lods.mips[&position].cached(level).unwrap(),
+        };
+
+        let mut cells = LodCells {
+            data: decimated(view.position, view.data),
+            neighbours: [None; 8],
+            blended: HashMap::new(),
+        };
+
+        for (i, &offset) in ChunkView::<L::Cell>::NEIGHBOURS.iter().enumerate() {
+            cells.neighbours[i] =
+                view.neighbours[i].map(|data| decimated(view.position + offset.into(), data));
+        }
+
+        if let Some(lerp) = &lods.lerp {
+            cells.blended = cells.blend(view.position, &coarser, level, lerp);
+        }
+
+        Some(cells)
+    }
+}
+
+impl<L: ChunkLayer> Default for ChunkLods<L> {
+    /// No levels of detail, every chunk has all of its cells.
+    fn default() -> Self {
+        Self {
+            distances: Vec::new(),
+            hysteresis: 0.0,
+            lerp: None,
+            levels: HashMap::new(),
+            mips: HashMap::new(),
+        }
+    }
+}
+
+/// The highest level of detail of chunks of `size`, where they
+/// still have an even number of cells along both axes.
+pub fn max_level(size: ChunkSize) -> u8 {
+    let twos = size
+        .width
+        .trailing_zeros()
+        .min(size.height.trailing_zeros());
+
+    twos.saturating_sub(1).min(8) as u8
+}
+
+/// The cells of a chunk and its neighbours at a level of detail, borrowed
+/// from the cached levels, see `ChunkLods::cells`.
+pub struct LodCells<'a, T> {
+    pub data: &'a ChunkData<T>,
+    /// The neighbours in the same order as `ChunkView::NEIGHBOURS`.
+    pub neighbours: [Option<&'a ChunkData<T>>; 8],
+    /// The cells along the borders to coarser neighbours, blended between
+    /// their cells, by their position in the view of the chunk.
+    pub blended: HashMap<CellPosition, T>,
+}
+
+impl<'a, T> LodCells<'a, T> {
+    /// A view of the cells with the blended borders, of the chunk at `position`.
+    pub fn view(&self, position: ChunkPosition) -> ChunkView<'_, T> {
+        let mut view = ChunkView::new(self.data).at(position);
+        view.neighbours = self.neighbours;
+        view.overlay = Some(&self.blended);
+        view
+    }
+
+    /// The blended cells along the borders of the chunk at `position`
+    /// and its `coarser` neighbours.
+    fn blend<F>(
+        &self,
+        position: ChunkPosition,
+        coarser: &[((i32, i32), u8)],
+        level: u8,
+        lerp: F,
+    ) -> HashMap<CellPosition, T>
+    where
+        F: Fn(&T, &T, f32) -> T,
+    {
+        let view = self.view(position);
+        let size = view.size();
+        let (top_left, bottom_right) = (size.top_left(), size.bottom_right());
+        let (width, height) = (size.width as i32, size.height as i32);
+
+        let mut blended = HashMap::new();
+
+        for &((dx, dy), neighbour) in coarser {
+            let step = 1 << (neighbour - level);
+
+            // The border is the left column of the chunk on the right,
+            // or the top row of the chunk below:
+            let (start, along, length) = match (dx, dy) {
+                (-1, 0) => (top_left, (0, -1), height),
+                (0, 1) => (top_left, (1, 0), width),
+                (1, 0) => (
+                    CellPosition::new(bottom_right.x + 1, top_left.y),
+                    (0, -1),
+                    height,
+                ),
+                _ => (
+                    CellPosition::new(top_left.x, bottom_right.y - 1),
+                    (1, 0),
+                    width,
+                ),
+            };
+
+            let at = |i: i32| CellPosition::new(start.x + along.0 * i, start.y + along.1 * i);
+
+            for i in (0..length).filter(|i| i % step != 0) {
+                let before = i - i % step;
+                let a = match view.get(at(before)) {
+                    Some(a) => a,
+                    None => continue,
+                };
+
+                // The last coarse cell is in a diagonal neighbour, which may not be
+                // loaded yet. Its square is not meshed, so the cells are clamped:
+                let b = view.get(at(before + step)).unwrap_or(a);
+
+                blended.insert(at(i), lerp(a, b, (i % step) as f32 / step as f32));
+            }
+        }
+
+        blended
+    }
+}
+
+impl<T: Clone> ChunkData<T> {
+    /// The chunk with one cell out of every `2^level` along each axis,
+    /// starting from the top left. The cell at `pos` is the cell at
+    /// `pos * 2^level` in the original chunk.
+    pub fn decimate(&self, level: u8) -> Self {
+        self.downsample(level, |cells| cells[0].clone())
+    }
+}
+
+impl<L> ChunkInfo<L> {
+    /// The sizes of the chunks at a level of detail, with `2^level`
+    /// times fewer cells along each axis that are as many times larger.
+    pub fn at_level(&self, level: u8) -> Self {
+        let step = 1 << level;
+
+        ChunkInfo::new(
+            ChunkSize::new(self.chunk_size.width / step, self.chunk_size.height / step),
+            CellSize::new(self.cell_size.width * step, self.cell_size.height * step),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::MarchingSquares;
+
+    #[derive(Default)]
+    struct Terrain;
+
+    impl ChunkLayer for Terrain {
+        type Cell = f32;
+    }
+
+    fn lerp(&a: &f32, &b: &f32, t: f32) -> f32 {
+        a + (b - a) * t
+    }
+
+    #[test]
+    fn levels_with_hysteresis() {
+        let lods = ChunkLods::<Terrain>::new(vec![100.0, 200.0], lerp).with_hysteresis(10.0);
+
+        assert_eq!(0, lods.select(0, 105.0));
+        assert_eq!(1, lods.select(0, 111.0));
+        assert_eq!(1, lods.select(1, 95.0));
+        assert_eq!(0, lods.select(1, 89.0));
+        assert_eq!(2, lods.select(0, 250.0));
+
+        assert_eq!(2, max_level(ChunkSize::new(16, 8)));
+        assert_eq!(0, max_level(ChunkSize::new(6, 6)));
+
+        let data = ChunkData::new_with_seed(ChunkSize::new(8, 8), |pos| pos.x * 100 + pos.y);
+        let coarse = data.decimate(1);
+        assert_eq!(ChunkSize::new(4, 4), coarse.size);
+        assert_eq!(Some(&(2 * 100 - 2)), coarse.get((1, -1)));
+        assert_eq!(Some(&(-4 * 100 + 4)), coarse.get((-2, 2)));
+    }
+
+    #[test]
+    fn stitched_borders() {
+        let size = ChunkSize::new(8, 8);
+        let cell_size = CellSize::new(1, 1);
+        let info = ChunkInfo::<Terrain>::new(size, cell_size);
+
+        // Solid below a curve, with the density changing faster
+        // than linearly along the border:
+        let chunk = |offset: i32| {
+            ChunkData::new_with_seed(size, |pos| {
+                let (x, y) = ((pos.x + offset) as f32, pos.y as f32);
+                1.5 + 0.1 * x - y * y * y / 8.0
+            })
+        };
+        let (left, right) = (chunk(0), chunk(8));
+        let positions = [ChunkPosition::new(0, 0), ChunkPosition::new(1, 0)];
+
+        let mut left_view = ChunkView::new(&left).at(positions[0]);
+        left_view.set_neighbour((1, 0), Some(&right));
+        let mut right_view = ChunkView::new(&right).at(positions[1]);
+        right_view.set_neighbour((-1, 0), Some(&left));
+
+        // The highest point of each mesh on the border, the left column of the right chunk:
+        let border = |lods: &mut ChunkLods<Terrain>| {
+            let marching = MarchingSquares::new(0.0);
+            let mut top = |view: &ChunkView<f32>, x: f32| {
+                let level = lods.level(view.position);
+                let cells = lods.cells(view).unwrap();
+                let mesh = marching.triangles(
+                    cells.view(view.position),
+                    info.at_level(level).cell_size,
+                    |&d| d,
+                );
+
+                mesh.positions
+                    .iter()
+                    .filter(|&&[px, _]| (px - x).abs() < 1e-5)
+                    .map(|&[_, y]| y)
+                    .fold(f32::MIN, f32::max)
+            };
+
+            (top(&left_view, 4.0), top(&right_view, -4.0))
+        };
+
+        let levels = |mut lods: ChunkLods<Terrain>| {
+            assert!(lods.update(positions[1], size, 20.0));
+            assert!(!lods.update(positions[0], size, 5.0));
+            assert_eq!(1, lods.level(positions[1]));
+            lods
+        };
+
+        // Keeping the coarse cells without blending between them cracks:
+        let mut lods = levels(ChunkLods::new(vec![10.0], |&a, _, _| a));
+        let (fine, coarse) = border(&mut lods);
+        assert!((fine - coarse).abs() > 0.1);
+
+        let mut lods = levels(ChunkLods::new(vec![10.0], lerp));
+        let (fine, coarse) = border(&mut lods);
+        assert!((fine - coarse).abs() < 1e-4);
+
+        // The last cell of the border is clamped to the last coarse cell,
+        // as the one after it is in the missing neighbour below:
+        let cells = lods.cells(&left_view).unwrap();
+        let blended = cells.view(positions[0]);
+        assert_eq!(right.get((-4, -2)), blended.get((4, -3)));
+        assert_ne!(right.get((-4, -3)), blended.get((4, -3)));
+
+        // The blended cells are only in the view, the cached levels are kept:
+        assert_eq!(
+            right.get((-4, -3)),
+            cells.neighbours[4].unwrap().get((-4, -3))
+        );
+    }
+
+    #[test]
+    fn cached_levels_follow_edits() {
+        let size = ChunkSize::new(8, 8);
+        let position = ChunkPosition::new(0, 0);
+        let mut data = ChunkData::new_with(size, 0.0);
+
+        let mut lods = ChunkLods::<Terrain>::new(vec![10.0], lerp);
+        lods.update(position, size, 20.0);
+
+        let coarse = lods.cells(&ChunkView::new(&data).at(position)).unwrap();
+        assert_eq!(Some(&0.0), coarse.data.get((1, -1)));
+
+        // The mesher takes the dirty rectangle, the cached level is still updated:
+        data.set((2, -2), 1.0);
+        data.take_dirty();
+
+        let coarse = lods.cells(&ChunkView::new(&data).at(position)).unwrap();
+        assert_eq!(Some(&1.0), coarse.data.get((1, -1)));
+    }
+}
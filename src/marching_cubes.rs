@@ -0,0 +1,360 @@
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::pipeline::PrimitiveTopology;
+
+use std::collections::HashMap;
+
+use crate::chain_links;
+
+/// The faces of a cube, as the corners counter-clockwise seen from outside.
+/// Corners are numbered with x in the lowest bit, then y, then z.
+const FACES: [[usize; 4]; 6] = [
+    [0, 4, 6, 2],
+    [1, 3, 7, 5],
+    [0, 1, 5, 4],
+    [2, 6, 7, 3],
+    [0, 2, 3, 1],
+    [4, 5, 7, 6],
+];
+
+/// Builds iso-surface meshes of 3D chunks with marching cubes.
+///
+/// There is no 3D chunk storage yet, so the density of every cell is read
+/// through a closure from its position in the chunk, from `[0, 0, 0]` to
+/// one less than the size of the chunk along every axis. Cells denser than
+/// `threshold` are solid, and the mesh is built between the cells, relative
+/// to the first cell of the chunk.
+///
+/// A chunk owns the cubes whose first corner is one of its cells. Cubes
+/// along the far sides of the chunk need the cells of the neighbouring
+/// chunks, so the closure should read them for positions one past the
+/// end of the chunk, and return `None` where they are not loaded. Normals
+/// come from the gradient of the density, which reads cells one before
+/// the start of the chunk when it can. As every cube only depends on the
+/// cells around it, neighbouring chunks place their shared vertices on the
+/// same points, with the same normals.
+///
+/// Instead of a table of cases, every face of a cube is split like the
+/// squares of `MarchingSquares`, and the crossings on the faces are joined
+/// into loops around the cube. Faces with two opposite solid corners are
+/// connected through their center if its average density is solid, like
+/// the saddle cases of `MarchingSquares`, so both cubes of a face agree
+/// and the surface is closed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MarchingCubes {
+    pub threshold: f32,
+}
+
+/// The triangles of an iso-surface, counter-clockwise
+/// seen from the empty side.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SurfaceMesh {
+    pub positions: Vec<[f32; 3]>,
+    /// Pointing away from the solid side.
+    pub normals: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+}
+
+/// The crossing on the edge from a cell to the next one along an axis.
+type EdgeKey = ([i32; 3], usize);
+
+impl MarchingCubes {
+    pub fn new(threshold: f32) -> Self {
+        Self { threshold }
+    }
+
+    /// The surface between the solid and empty cells of a chunk of `size`
+    /// cells along x, y and z, with `density` reading the density of a cell.
+    pub fn triangles<F>(&self, size: [usize; 3], cell_size: Vec3, density: F) -> SurfaceMesh
+    where
+        F: Fn([i32; 3]) -> Option<f32>,
+    {
+        let mut surface = SurfaceMesh::default();
+        let mut cache: HashMap<EdgeKey, u32> = HashMap::new();
+
+        let [w, h, d] = [size[0] as i32, size[1] as i32, size[2] as i32];
+        let offset = |[x, y, z]: [i32; 3], axis: usize| {
+            let mut pos = [x, y, z];
+            pos[axis] += 1;
+            pos
+        };
+
+        for z in 0..d {
+            for y in 0..h {
+                for x in 0..w {
+                    let corner = |i: usize| {
+                        [
+                            x + (i & 1) as i32,
+                            y + (i >> 1 & 1) as i32,
+                            z + (i >> 2) as i32,
+                        ]
+                    };
+
+                    let mut densities = [0.0; 8];
+                    let mut complete = true;
+
+                    for (i, density_at) in densities.iter_mut().enumerate() {
+                        match density(corner(i)) {
+                            Some(value) => *density_at = value,
+                            None => complete = false,
+                        }
+                    }
+
+                    let solid: Vec<bool> = densities
+                        .iter()
+                        .map(|&value| value > self.threshold)
+                        .collect();
+
+                    if !complete || solid.iter().all(|&s| s == solid[0]) {
+                        continue;
+                    }
+
+                    // Link every crossing where the surface enters a face,
+                    // going counter-clockwise, to where it leaves it:
+                    let mut next = HashMap::new();
+
+                    for face in &FACES {
+                        let crossings: Vec<usize> = (0..4)
+                            .filter(|&k| solid[face[k]] != solid[face[(k + 1) % 4]])
+                            .collect();
+
+                        let edge = |k: usize| {
+                            let (a, b) = (face[k % 4], face[(k + 1) % 4]);
+                            let axis = (a ^ b).trailing_zeros() as usize;
+                            (corner(a.min(b)), axis)
+                        };
+
+                        match crossings.len() {
+                            2 => {
+                                let (a, b) = (crossings[0], crossings[1]);
+                                let (enter, leave) = if solid[face[a]] { (b, a) } else { (a, b) };
+                                next.insert(edge(enter), edge(leave));
+                            }
+                            4 => {
+                                let average = face.iter().map(|&i| densities[i]).sum::<f32>() / 4.0;
+                                let connected = average > self.threshold;
+
+                                for k in 0..4 {
+                                    // Cut off the empty corners if the solid ones are
+                                    // connected, and the solid corners otherwise:
+                                    if solid[face[k]] != connected {
+                                        let (enter, leave) =
+                                            if connected { (k, k + 3) } else { (k + 3, k) };
+                                        next.insert(edge(enter), edge(leave));
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    for (edges, _) in chain_links(&next) {
+                        let mut vertex = |(pos, axis): EdgeKey| {
+                            *cache.entry((pos, axis)).or_insert_with(|| {
+                                let end = offset(pos, axis);
+                                self.push_vertex(&mut surface, pos, end, cell_size, &density)
+                            })
+                        };
+
+                        let indices: Vec<u32> = edges.into_iter().map(&mut vertex).collect();
+
+                        for i in 1..indices.len() - 1 {
+                            surface.indices.extend_from_slice(&[
+                                indices[0],
+                                indices[i],
+                                indices[i + 1],
+                            ]);
+                        }
+                    }
+                }
+            }
+        }
+
+        surface
+    }
+
+    /// A triangle list `Mesh` of the surface of a chunk, see `triangles`.
+    pub fn mesh<F>(&self, size: [usize; 3], cell_size: Vec3, density: F) -> Mesh
+    where
+        F: Fn([i32; 3]) -> Option<f32>,
+    {
+        self.triangles(size, cell_size, density).into_mesh()
+    }
+
+    /// Add the vertex where the surface crosses the edge between the cells
+    /// at `a` and `b`, with the normal from the gradient at both ends.
+    fn push_vertex<F>(
+        &self,
+        surface: &mut SurfaceMesh,
+        a: [i32; 3],
+        b: [i32; 3],
+        cell_size: Vec3,
+        density: F,
+    ) -> u32
+    where
+        F: Fn([i32; 3]) -> Option<f32>,
+    {
+        let (da, db) = (density(a).unwrap(), density(b).unwrap());
+        let t = if (db - da).abs() <= f32::EPSILON {
+            0.5
+        } else {
+            ((self.threshold - da) / (db - da)).clamp(0.0, 1.0)
+        };
+
+        let point = |[x, y, z]: [i32; 3]| Vec3::new(x as f32, y as f32, z as f32) * cell_size;
+        let position = point(a) + (point(b) - point(a)) * t;
+
+        let (ga, gb) = (
+            gradient(a, cell_size, &density),
+            gradient(b, cell_size, &density),
+        );
+        let gradient = ga + (gb - ga) * t;
+        let normal = if gradient.length() <= f32::EPSILON {
+            Vec3::zero()
+        } else {
+            -gradient.normalize()
+        };
+
+        surface.positions.push([position.x, position.y, position.z]);
+        surface.normals.push([normal.x, normal.y, normal.z]);
+        surface.positions.len() as u32 - 1
+    }
+}
+
+/// The gradient of the density at a cell, from the differences to its
+/// neighbours along every axis, or to the one neighbour that is loaded.
+fn gradient<F>(pos: [i32; 3], cell_size: Vec3, density: F) -> Vec3
+where
+    F: Fn([i32; 3]) -> Option<f32>,
+{
+    let center = density(pos).unwrap_or(0.0);
+    let size = [cell_size.x, cell_size.y, cell_size.z];
+    let mut gradient = [0.0; 3];
+
+    for axis in 0..3 {
+        let mut before = pos;
+        let mut after = pos;
+        before[axis] -= 1;
+        after[axis] += 1;
+
+        gradient[axis] = match (density(before), density(after)) {
+            (Some(before), Some(after)) => (after - before) / (2.0 * size[axis]),
+            (None, Some(after)) => (after - center) / size[axis],
+            (Some(before), None) => (center - before) / size[axis],
+            (None, None) => 0.0,
+        };
+    }
+
+    Vec3::new(gradient[0], gradient[1], gradient[2])
+}
+
+impl SurfaceMesh {
+    /// The triangles as `[a, b, c]` positions.
+    pub fn triangles(&self) -> impl Iterator<Item = [[f32; 3]; 3]> + '_ {
+        self.indices.chunks_exact(3).map(move |tri| {
+            [
+                self.positions[tri[0] as usize],
+                self.positions[tri[1] as usize],
+                self.positions[tri[2] as usize],
+            ]
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// A triangle list `Mesh` with the positions and normals of the vertices.
+    pub fn into_mesh(self) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
+        mesh.set_indices(Some(Indices::U32(self.indices)));
+
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether every edge of `surface` is shared by exactly two triangles,
+    /// going opposite ways, with vertices on the same point welded.
+    fn is_closed(surface: &SurfaceMesh) -> bool {
+        let key = |[x, y, z]: [f32; 3]| {
+            let round = |v: f32| (v * 1e4).round() as i64;
+            (round(x), round(y), round(z))
+        };
+
+        let mut edges = HashMap::new();
+
+        for tri in surface.triangles() {
+            for i in 0..3 {
+                let edge = (key(tri[i]), key(tri[(i + 1) % 3]));
+                *edges.entry(edge).or_insert(0) += 1;
+            }
+        }
+
+        edges
+            .iter()
+            .all(|(&(a, b), &count)| count == 1 && edges.get(&(b, a)) == Some(&1))
+    }
+
+    /// A lumpy ball around `center`, in a grid from 0 to 8 along every axis.
+    fn ball(center: Vec3) -> impl Fn([i32; 3]) -> Option<f32> {
+        move |[x, y, z]| {
+            if [x, y, z].iter().any(|&v| !(0..=8).contains(&v)) {
+                return None;
+            }
+
+            let pos = Vec3::new(x as f32, y as f32, z as f32);
+            let lumps = (x as f32 * 1.7).sin() * (y as f32 * 2.3).cos() * (z as f32 * 0.9).sin();
+            Some(3.0 - (pos - center).length() + lumps)
+        }
+    }
+
+    #[test]
+    fn closed_surfaces() {
+        let center = Vec3::new(4.0, 4.0, 4.0);
+        let surface = MarchingCubes::new(0.0).triangles([8, 8, 8], Vec3::one(), ball(center));
+
+        assert!(!surface.is_empty());
+        assert!(is_closed(&surface));
+
+        // Normals point out of the ball:
+        for (&[x, y, z], &normal) in surface.positions.iter().zip(&surface.normals) {
+            let outwards = Vec3::new(x, y, z) - center;
+            assert!(Vec3::from(normal).dot(outwards) > 0.0);
+        }
+
+        // Welded vertices are shared by about 6 triangles:
+        assert!(surface.indices.len() > surface.positions.len() * 5);
+    }
+
+    #[test]
+    fn seamless_chunks() {
+        let marching = MarchingCubes::new(0.0);
+        let field = ball(Vec3::new(4.0, 4.2, 3.9));
+
+        // The two halves of the ball, split at x = 4:
+        let left = marching.triangles([4, 8, 8], Vec3::one(), &field);
+        let mut right =
+            marching.triangles([4, 8, 8], Vec3::one(), |[x, y, z]| field([x + 4, y, z]));
+
+        assert!(!is_closed(&left) && !is_closed(&right));
+
+        let offset = left.positions.len() as u32;
+        for position in &mut right.positions {
+            position[0] += 4.0;
+        }
+
+        let mut whole = left;
+        whole.positions.append(&mut right.positions);
+        whole
+            .indices
+            .extend(right.indices.iter().map(|index| index + offset));
+
+        assert!(is_closed(&whole));
+    }
+}
@@ -0,0 +1,1382 @@
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+use bevy::render::pipeline::PrimitiveTopology;
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use crate::{
+    CellPosition, CellRect, CellSize, ChunkPosition, ChunkSize, ChunkView, Contour,
+    ContourSegments, GlobalCellPosition, UvMapping, ATTRIBUTE_COLOR,
+};
+
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+enum Side {
+    Top,
+    Right,
+    Bottom,
+    Left,
+    TopLeft,
+    TopRight,
+    BotRight,
+    BotLeft,
+}
+
+use Side::*;
+
+impl Side {
+    /// Whether the side is the middle of an edge rather than a corner.
+    fn is_edge(self) -> bool {
+        matches!(self, Top | Right | Bottom | Left)
+    }
+
+    /// The corners at both ends of the side (top left, top right, bottom
+    /// right, bottom left), the same corner twice for a corner side.
+    fn corners(self) -> (usize, usize) {
+        match self {
+            Top => (0, 1),
+            Right => (1, 2),
+            Bottom => (2, 3),
+            Left => (3, 0),
+            TopLeft => (0, 0),
+            TopRight => (1, 1),
+            BotRight => (2, 2),
+            BotLeft => (3, 3),
+        }
+    }
+}
+
+/// A vertex shared between neighbouring squares: the center of a cell,
+/// or the contour crossing on the edge to the right of or below a cell.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub(crate) enum VertexKey {
+    Cell(CellPosition),
+    Right(CellPosition),
+    Below(CellPosition),
+}
+
+impl VertexKey {
+    /// The vertex at `side` of the square with its top left corner at `cell`.
+    fn new(side: Side, cell: CellPosition) -> Self {
+        let (x, y) = (cell.x, cell.y);
+
+        match side {
+            Top => VertexKey::Right(cell),
+            Right => VertexKey::Below(CellPosition::new(x + 1, y)),
+            Bottom => VertexKey::Right(CellPosition::new(x, y - 1)),
+            Left => VertexKey::Below(cell),
+            TopLeft => VertexKey::Cell(cell),
+            TopRight => VertexKey::Cell(CellPosition::new(x + 1, y)),
+            BotRight => VertexKey::Cell(CellPosition::new(x + 1, y - 1)),
+            BotLeft => VertexKey::Cell(CellPosition::new(x, y - 1)),
+        }
+    }
+
+    /// The key of the same vertex in every chunk, for a vertex of the chunk at `chunk`.
+    pub(crate) fn to_global(
+        self,
+        chunk_size: ChunkSize,
+        chunk: ChunkPosition,
+    ) -> (u8, GlobalCellPosition) {
+        let global = |cell| GlobalCellPosition::from_chunk(chunk_size, chunk, cell);
+
+        match self {
+            VertexKey::Cell(cell) => (0, global(cell)),
+            VertexKey::Right(cell) => (1, global(cell)),
+            VertexKey::Below(cell) => (2, global(cell)),
+        }
+    }
+}
+
+/// The triangles of every case, indexed by the solid corners of a square
+/// (top left, top right, bottom right, bottom left from the highest bit).
+/// The last two cases are the saddles 5 and 10 with their center empty.
+const TRI_LUT: [&[Side]; 18] = [
+    &[],
+    &[Left, BotLeft, Bottom],
+    &[Bottom, BotRight, Right],
+    &[Left, BotLeft, BotRight, Left, BotRight, Right],
+    &[Top, Right, TopRight],
+    &[
+        Left, BotLeft, Bottom, Left, Bottom, Right, Left, Right, Top, Top, Right, TopRight,
+    ],
+    &[Top, BotRight, TopRight, Bottom, BotRight, Top],
+    &[
+        BotLeft, BotRight, TopRight, TopRight, Top, Left, Left, BotLeft, TopRight,
+    ],
+    &[TopLeft, Left, Top],
+    &[TopLeft, BotLeft, Bottom, Bottom, Top, TopLeft],
+    &[
+        TopLeft, Left, Top, Left, Bottom, Top, Top, Bottom, Right, Right, Bottom, BotRight,
+    ],
+    &[
+        TopLeft, BotLeft, BotRight, TopLeft, BotRight, Right, Right, Top, TopLeft,
+    ],
+    &[TopLeft, Right, TopRight, TopLeft, Left, Right],
+    &[
+        TopRight, TopLeft, BotLeft, BotLeft, Bottom, Right, Right, TopRight, BotLeft,
+    ],
+    &[
+        TopLeft, BotRight, TopRight, TopLeft, Left, Bottom, Bottom, BotRight, TopLeft,
+    ],
+    &[TopLeft, BotLeft, BotRight, BotRight, TopRight, TopLeft],
+    &[Left, BotLeft, Bottom, Top, Right, TopRight],
+    &[Bottom, BotRight, Right, TopLeft, Left, Top],
+];
+
+/// Where the contour crosses the edge between a solid and an empty corner.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum EdgePlacement {
+    /// The middle of the edge, giving blocky 45° contours.
+    Midpoint,
+    /// The point where the density linearly interpolated
+    /// between the corners crosses the threshold.
+    #[default]
+    Interpolated,
+    /// Halfway between the interpolated point and the midpoint, softening
+    /// the contour where the density changes sharply between cells.
+    Smoothed,
+}
+
+impl EdgePlacement {
+    /// How far along the edge from the corner with density `a`
+    /// to the corner with density `b` the contour crosses it.
+    pub fn crossing(&self, a: f32, b: f32, threshold: f32) -> f32 {
+        let interpolated = || {
+            if (b - a).abs() <= f32::EPSILON {
+                return 0.5;
+            }
+
+            ((threshold - a) / (b - a)).clamp(0.0, 1.0)
+        };
+
+        match self {
+            EdgePlacement::Midpoint => 0.5,
+            EdgePlacement::Interpolated => interpolated(),
+            EdgePlacement::Smoothed => (interpolated() + 0.5) / 2.0,
+        }
+    }
+}
+
+/// Builds chunk meshes with marching squares.
+///
+/// Every cell is a sample of a density field at its center, and cells
+/// denser than `threshold` are solid. The mesh covers the solid part of
+/// the squares between the centers of neighbouring cells, relative to the
+/// center of the chunk. Squares on the right and bottom edges of the chunk
+/// are only built when the `ChunkView` has the neighbours they need.
+///
+/// Vertices on the corners and edges of squares are shared between
+/// the squares around them, so the mesh is connected.
+///
+/// Meshes face the camera (normals along +Z) and have texture
+/// coordinates mapped with `uvs`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MarchingSquares {
+    pub threshold: f32,
+    pub edges: EdgePlacement,
+    pub uvs: UvMapping,
+}
+
+impl MarchingSquares {
+    pub fn new(threshold: f32) -> Self {
+        Self {
+            threshold,
+            edges: EdgePlacement::default(),
+            uvs: UvMapping::default(),
+        }
+    }
+
+    pub fn with_edges(mut self, edges: EdgePlacement) -> Self {
+        self.edges = edges;
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: UvMapping) -> Self {
+        self.uvs = uvs;
+        self
+    }
+
+    /// The triangles covering the solid part of `view`, with
+    /// `density` reading the density of a single cell.
+    pub fn triangles<'a, T: 'a, F>(
+        &self,
+        view: impl Into<ChunkView<'a, T>>,
+        cell_size: CellSize,
+        density: F,
+    ) -> ContourMesh
+    where
+        F: Fn(&T) -> f32,
+    {
+        let view = view.into();
+        let mut contour = ContourMesh::default();
+        let mut cache = HashMap::new();
+
+        for_each_square(&view, density, |cell, corners| {
+            self.square(&mut contour, &mut cache, cell, corners, cell_size);
+        });
+
+        contour.map_uvs(self.uvs, &view, cell_size);
+
+        contour
+    }
+
+    /// The contours between the solid and empty parts of `view`, with the
+    /// solid side on their left. Contours around solid areas are
+    /// counter-clockwise and the ones around holes are clockwise.
+    ///
+    /// Contours that leave the squares of the chunk are open, use
+    /// `ChunkContours` to stitch them with the contours of its neighbours.
+    pub fn contours<'a, T: 'a, F>(
+        &self,
+        view: impl Into<ChunkView<'a, T>>,
+        cell_size: CellSize,
+        density: F,
+    ) -> Vec<Contour>
+    where
+        F: Fn(&T) -> f32,
+    {
+        let mut segments = ContourSegments::default();
+
+        for_each_square(&view.into(), density, |cell, corners| {
+            for (a, b) in self.square_segments(cell, corners, cell_size) {
+                segments.insert(a, b);
+            }
+        });
+
+        segments.chain()
+    }
+
+    /// The contour segments of the square with its top left corner at `cell`.
+    fn square_segments(
+        &self,
+        cell: CellPosition,
+        corners: [f32; 4],
+        cell_size: CellSize,
+    ) -> Vec<Segment> {
+        let sides = TRI_LUT[self.case(corners)];
+        let offset = Vec2::new(cell.x as f32, cell.y as f32) * cell_size.as_vec2();
+
+        // Triangles are counter-clockwise, so their edges between two edge
+        // crossings that are not shared with another triangle of the square
+        // are the contour, with the solid side on their left:
+        let edges: Vec<(Side, Side)> = sides
+            .chunks_exact(3)
+            .flat_map(|tri| vec![(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])])
+            .filter(|&(a, b)| a.is_edge() && b.is_edge())
+            .collect();
+
+        let point = |side| offset + self.side_point(side, corners, cell_size);
+
+        edges
+            .iter()
+            .filter(|&&(a, b)| !edges.contains(&(b, a)))
+            .map(|&(a, b)| {
+                (
+                    (VertexKey::new(a, cell), point(a)),
+                    (VertexKey::new(b, cell), point(b)),
+                )
+            })
+            .collect()
+    }
+
+    /// A triangle list `Mesh` covering the solid part of `view`.
+    pub fn mesh<'a, T: 'a, F>(
+        &self,
+        view: impl Into<ChunkView<'a, T>>,
+        cell_size: CellSize,
+        density: F,
+    ) -> Mesh
+    where
+        F: Fn(&T) -> f32,
+    {
+        self.triangles(view, cell_size, density).into_mesh()
+    }
+
+    /// Update `mesh` to the triangles of `view`, like `triangles`, only
+    /// building again the squares with a corner in `dirty`. Vertices
+    /// and indices are patched in place.
+    ///
+    /// The whole mesh is built when `dirty` is `None`, or when `view` is
+    /// not the chunk, the size or the loaded neighbours `mesh` was built
+    /// from. Cells of the neighbours that changed must be marked dirty in
+    /// the chunks that see them, see `dependent_chunks`.
+    pub fn remesh<'a, T: 'a, F>(
+        &self,
+        mesh: &mut IncrementalMesh,
+        view: impl Into<ChunkView<'a, T>>,
+        cell_size: CellSize,
+        density: F,
+        dirty: Option<CellRect>,
+    ) where
+        F: Fn(&T) -> f32,
+    {
+        let view = view.into();
+        let mut source = MeshSource {
+            squares: *self,
+            position: view.position,
+            size: view.size(),
+            cell_size,
+            neighbours: [false; 8],
+        };
+
+        for (loaded, neighbour) in source.neighbours.iter_mut().zip(&view.neighbours) {
+            *loaded = neighbour.is_some();
+        }
+
+        match dirty {
+            Some(dirty) if mesh.source == Some(source) => {
+                self.patch(mesh, &view, cell_size, density, dirty)
+            }
+            _ => {
+                *mesh = self.incremental(&view, cell_size, density);
+                mesh.source = Some(source);
+            }
+        }
+    }
+
+    /// Build the mesh of `view`, remembering the triangles of every square.
+    fn incremental<T, F>(
+        &self,
+        view: &ChunkView<T>,
+        cell_size: CellSize,
+        density: F,
+    ) -> IncrementalMesh
+    where
+        F: Fn(&T) -> f32,
+    {
+        let mut mesh = IncrementalMesh::default();
+        let size = view.size();
+        mesh.squares = vec![0..0; size.width * size.height];
+
+        mesh.outlines = vec![Vec::new(); size.width * size.height];
+        mesh.outlines_changed = true;
+
+        for_each_square(view, density, |cell, corners| {
+            let start = mesh.contour.indices.len();
+            self.square(
+                &mut mesh.contour,
+                &mut mesh.vertices,
+                cell,
+                corners,
+                cell_size,
+            );
+
+            let idx = view.data.convert_to_idx(cell);
+            mesh.squares[idx] = start..mesh.contour.indices.len();
+
+            let segments = self.square_segments(cell, corners, cell_size);
+            for &(a, b) in &segments {
+                mesh.segments.insert(a, b);
+            }
+            mesh.outlines[idx] = segments;
+        });
+
+        // Squares missing a corner have no triangles, but keep their place:
+        let mut end = 0;
+        for square in &mut mesh.squares {
+            if square.start == square.end {
+                *square = end..end;
+            }
+
+            end = square.end;
+        }
+
+        mesh.contour.map_uvs(self.uvs, view, cell_size);
+
+        mesh
+    }
+
+    /// Build again the squares of `mesh` with a corner in `dirty`, one row at a time.
+    fn patch<T, F>(
+        &self,
+        mesh: &mut IncrementalMesh,
+        view: &ChunkView<T>,
+        cell_size: CellSize,
+        density: F,
+        dirty: CellRect,
+    ) where
+        F: Fn(&T) -> f32,
+    {
+        let size = view.size();
+        let bounds = CellRect::new(size.top_left(), size.bottom_right());
+        let squares = CellRect::new(
+            CellPosition::new(dirty.min.x - 1, dirty.min.y),
+            CellPosition::new(dirty.max.x, dirty.max.y + 1),
+        );
+        let squares = match squares.intersection(bounds) {
+            Some(squares) => squares,
+            None => return,
+        };
+
+        let IncrementalMesh {
+            contour,
+            vertices,
+            squares: ranges,
+            segments,
+            outlines,
+            outlines_changed,
+            written,
+            ..
+        } = mesh;
+
+        // The segments of every square are removed first, as the squares
+        // built again can start their segments on the same vertices:
+        for cell in squares.iter() {
+            for &((a, _), _) in &outlines[view.data.convert_to_idx(cell)] {
+                segments.remove(a);
+            }
+        }
+
+        for y in (squares.min.y..=squares.max.y).rev() {
+            let row = CellRect::new(
+                CellPosition::new(squares.min.x, y),
+                CellPosition::new(squares.max.x, y),
+            );
+            let first = view.data.convert_to_idx(row.min);
+            let last = view.data.convert_to_idx(row.max);
+            let replaced = ranges[first].start..ranges[last].end;
+
+            let mut indices = Vec::new();
+            for (i, cell) in row.iter().enumerate() {
+                let start = replaced.start + indices.len();
+
+                let corners = square_corners(view, cell, &density);
+                let outline = corners
+                    .map(|corners| self.square_segments(cell, corners, cell_size))
+                    .unwrap_or_default();
+
+                for &(a, b) in &outline {
+                    segments.insert(a, b);
+                }
+
+                let old = &mut outlines[first + i];
+                if *old != outline {
+                    *old = outline;
+                    *outlines_changed = true;
+                }
+
+                if let Some(corners) = corners {
+                    self.square_vertices(cell, corners, cell_size, |key, point, cell| {
+                        let uv = self.uvs.uv(size, cell_size, view.position, point);
+                        let vertex = match vertices.get(&key) {
+                            Some(&vertex) => {
+                                contour.positions[vertex as usize] = [point.x, point.y];
+                                contour.cells[vertex as usize] = cell;
+                                contour.uvs[vertex as usize] = uv;
+
+                                if let Some(written) = written.as_mut() {
+                                    written.vertices.push(vertex);
+                                }
+                                vertex
+                            }
+                            None => {
+                                let vertex = contour.push_vertex(point, cell);
+                                contour.uvs.push(uv);
+                                vertices.insert(key, vertex);
+                                vertex
+                            }
+                        };
+
+                        indices.push(vertex);
+                    });
+                }
+
+                ranges[first + i] = start..replaced.start + indices.len();
+            }
+
+            // Move the triangles of the squares after the row along:
+            let shift = indices.len() as isize - replaced.len() as isize;
+            if let Some(written) = written.as_mut() {
+                written.splices.push((replaced.clone(), indices.clone()));
+            }
+            contour.indices.splice(replaced, indices);
+
+            if shift != 0 {
+                for range in &mut ranges[last + 1..] {
+                    let start = (range.start as isize + shift) as usize;
+                    let end = (range.end as isize + shift) as usize;
+                    *range = start..end;
+                }
+            }
+        }
+    }
+
+    /// The triangles of every material in `view`, with `sample` reading the
+    /// material and density of a single cell. Materials are sorted.
+    ///
+    /// The solid part of `view` is meshed once, and every vertex takes the
+    /// material of its cell. Triangles with vertices of several materials
+    /// are split halfway between them, so neighbouring materials meet on the
+    /// same points without gaps. Use `ContourMesh::append` and
+    /// `ContourMesh::with_colors` to join them into one mesh with the
+    /// material of every vertex.
+    pub fn materials<'a, T: 'a, M, F>(
+        &self,
+        view: impl Into<ChunkView<'a, T>>,
+        cell_size: CellSize,
+        sample: F,
+    ) -> Vec<(M, ContourMesh)>
+    where
+        M: Copy + Ord,
+        F: Fn(&T) -> (M, f32),
+    {
+        let view = view.into();
+        let solid = self.triangles(view, cell_size, |cell| sample(cell).1);
+
+        let material = |vertex: u32| {
+            let cell = solid.cells[vertex as usize];
+            view.get(cell).map(|cell| sample(cell).0).unwrap()
+        };
+
+        let mut materials: BTreeMap<M, MaterialMesh> = BTreeMap::new();
+
+        for tri in solid.indices.chunks_exact(3) {
+            let [a, b, c] = [tri[0], tri[1], tri[2]];
+            let [ma, mb, mc] = [material(a), material(b), material(c)];
+            let mut add = |m: M, owner: u32, points: &[SplitPoint]| {
+                materials
+                    .entry(m)
+                    .or_default()
+                    .polygon(&solid, owner, points)
+            };
+
+            use SplitPoint::{Center, Half, Vertex};
+
+            // Rotate the triangle so the vertices of the same material come first:
+            let (a, b, c, m, n) = match (ma == mb, mb == mc, mc == ma) {
+                (true, true, _) => {
+                    add(ma, a, &[Vertex(a), Vertex(b), Vertex(c)]);
+                    continue;
+                }
+                (true, false, _) => (a, b, c, ma, mc),
+                (false, true, _) => (b, c, a, mb, ma),
+                (false, false, true) => (c, a, b, mc, mb),
+                (false, false, false) => {
+                    let center = Center(a, b, c);
+                    add(ma, a, &[Vertex(a), Half(a, b), center, Half(c, a)]);
+                    add(mb, b, &[Vertex(b), Half(b, c), center, Half(a, b)]);
+                    add(mc, c, &[Vertex(c), Half(c, a), center, Half(b, c)]);
+                    continue;
+                }
+            };
+
+            add(m, a, &[Vertex(a), Vertex(b), Half(b, c), Half(c, a)]);
+            add(n, c, &[Half(b, c), Vertex(c), Half(c, a)]);
+        }
+
+        materials
+            .into_iter()
+            .map(|(material, mesh)| {
+                let mut contour = mesh.contour;
+                contour.map_uvs(self.uvs, &view, cell_size);
+
+                (material, contour)
+            })
+            .collect()
+    }
+
+    /// Add the triangles of the square with `corners` (top left, top right,
+    /// bottom right, bottom left) and its top left corner at `cell`, reusing
+    /// the vertices in `cache` that neighbouring squares already added.
+    fn square(
+        &self,
+        contour: &mut ContourMesh,
+        cache: &mut HashMap<VertexKey, u32>,
+        cell: CellPosition,
+        corners: [f32; 4],
+        size: CellSize,
+    ) {
+        self.square_vertices(cell, corners, size, |key, point, cell| {
+            let vertex = *cache
+                .entry(key)
+                .or_insert_with(|| contour.push_vertex(point, cell));
+
+            contour.indices.push(vertex);
+        });
+    }
+
+    /// Call `f` with the key, position and cell of every vertex of the
+    /// triangles of the square with `corners` and its top left corner at `cell`.
+    fn square_vertices<F>(&self, cell: CellPosition, corners: [f32; 4], size: CellSize, mut f: F)
+    where
+        F: FnMut(VertexKey, Vec2, CellPosition),
+    {
+        let offset = Vec2::new(cell.x as f32, cell.y as f32) * size.as_vec2();
+        let corner_cells = [
+            cell,
+            CellPosition::new(cell.x + 1, cell.y),
+            CellPosition::new(cell.x + 1, cell.y - 1),
+            CellPosition::new(cell.x, cell.y - 1),
+        ];
+
+        for &side in TRI_LUT[self.case(corners)] {
+            // Vertices on an edge take after its denser, solid, corner:
+            let (a, b) = side.corners();
+            let solid = if corners[a] >= corners[b] { a } else { b };
+
+            f(
+                VertexKey::new(side, cell),
+                offset + self.side_point(side, corners, size),
+                corner_cells[solid],
+            );
+        }
+    }
+
+    /// The index into `TRI_LUT` of a square with `corners`.
+    fn case(&self, corners: [f32; 4]) -> usize {
+        let case = corners.iter().fold(0, |case, &corner| {
+            case << 1 | (corner > self.threshold) as usize
+        });
+
+        let average = corners.iter().sum::<f32>() / 4.0;
+        match case {
+            5 if average <= self.threshold => 16,
+            10 if average <= self.threshold => 17,
+            case => case,
+        }
+    }
+
+    /// The point of a square at `side`, relative to its top left corner.
+    fn side_point(&self, side: Side, corners: [f32; 4], size: CellSize) -> Vec2 {
+        let (w, h) = (size.width as f32, size.height as f32);
+        let points = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(w, 0.0),
+            Vec2::new(w, -h),
+            Vec2::new(0.0, -h),
+        ];
+
+        match side.corners() {
+            (a, b) if a == b => points[a],
+            (a, b) => {
+                let t = self.edges.crossing(corners[a], corners[b], self.threshold);
+                points[a] + (points[b] - points[a]) * t
+            }
+        }
+    }
+}
+
+/// A point of a triangle split between materials: one of its vertices,
+/// halfway along one of its edges, or its center.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+enum SplitPoint {
+    Vertex(u32),
+    Half(u32, u32),
+    Center(u32, u32, u32),
+}
+
+impl SplitPoint {
+    /// The same point for every triangle that has it.
+    fn key(self) -> Self {
+        match self {
+            SplitPoint::Half(a, b) => SplitPoint::Half(a.min(b), a.max(b)),
+            point => point,
+        }
+    }
+
+    fn position(self, contour: &ContourMesh) -> Vec2 {
+        let position = |vertex: u32| Vec2::from(contour.positions[vertex as usize]);
+
+        match self {
+            SplitPoint::Vertex(a) => position(a),
+            SplitPoint::Half(a, b) => (position(a) + position(b)) / 2.0,
+            SplitPoint::Center(a, b, c) => (position(a) + position(b) + position(c)) / 3.0,
+        }
+    }
+}
+
+/// The triangles of a single material, split from the triangles of the solid mesh.
+#[derive(Default)]
+struct MaterialMesh {
+    contour: ContourMesh,
+    cache: HashMap<SplitPoint, u32>,
+}
+
+impl MaterialMesh {
+    /// Add a convex polygon, with the points not on a vertex
+    /// belonging to the cell of the vertex `owner`.
+    fn polygon(&mut self, solid: &ContourMesh, owner: u32, points: &[SplitPoint]) {
+        let (contour, cache) = (&mut self.contour, &mut self.cache);
+        let indices: Vec<u32> = points
+            .iter()
+            .map(|&point| {
+                *cache.entry(point.key()).or_insert_with(|| {
+                    let cell = match point {
+                        SplitPoint::Vertex(vertex) => solid.cells[vertex as usize],
+                        _ => solid.cells[owner as usize],
+                    };
+
+                    contour.push_vertex(point.position(solid), cell)
+                })
+            })
+            .collect();
+
+        for i in 1..indices.len() - 1 {
+            contour
+                .indices
+                .extend_from_slice(&[indices[0], indices[i], indices[i + 1]]);
+        }
+    }
+}
+
+/// Call `f` with the top left cell and the corner densities of every
+/// square of `view` that has all of its corners.
+fn for_each_square<T, D, F>(view: &ChunkView<T>, density: D, mut f: F)
+where
+    D: Fn(&T) -> f32,
+    F: FnMut(CellPosition, [f32; 4]),
+{
+    let bounds = CellRect::new(view.size().top_left(), view.size().bottom_right());
+
+    for cell in bounds.iter() {
+        if let Some(corners) = square_corners(view, cell, &density) {
+            f(cell, corners);
+        }
+    }
+}
+
+/// The corner densities of the square of `view` with its top left corner
+/// at `cell`, or `None` if one of its corners is not loaded.
+fn square_corners<T, D>(view: &ChunkView<T>, cell: CellPosition, density: D) -> Option<[f32; 4]>
+where
+    D: Fn(&T) -> f32,
+{
+    let sample = |dx, dy| {
+        view.get(CellPosition::new(cell.x + dx, cell.y + dy))
+            .map(&density)
+    };
+
+    Some([sample(0, 0)?, sample(1, 0)?, sample(1, -1)?, sample(0, -1)?])
+}
+
+/// A contour segment, from the first vertex to the second.
+type Segment = ((VertexKey, Vec2), (VertexKey, Vec2));
+
+/// What an `IncrementalMesh` was built from.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct MeshSource {
+    squares: MarchingSquares,
+    position: ChunkPosition,
+    size: ChunkSize,
+    cell_size: CellSize,
+    neighbours: [bool; 8],
+}
+
+/// The marching squares mesh of a chunk along with the triangles of
+/// every square, so it can be patched where cells changed instead
+/// of being built again, see `MarchingSquares::remesh`.
+///
+/// Vertices are kept once no triangle uses them anymore, and
+/// reused if the contour crosses the same edge again.
+#[derive(Debug, Default, Clone)]
+pub struct IncrementalMesh {
+    contour: ContourMesh,
+    vertices: HashMap<VertexKey, u32>,
+    /// The range of `contour.indices` of every square, by its top left cell.
+    squares: Vec<Range<usize>>,
+    source: Option<MeshSource>,
+    /// The changes since the mesh was last written, see `write_mesh`.
+    written: Option<MeshWrites>,
+    /// The contour segments of every square, joined in `segments`.
+    outlines: Vec<Vec<Segment>>,
+    segments: ContourSegments,
+    outlines_changed: bool,
+}
+
+impl IncrementalMesh {
+    pub fn contour(&self) -> &ContourMesh {
+        &self.contour
+    }
+
+    /// The contours of the mesh, like `MarchingSquares::contours`, if a
+    /// segment changed since they were last taken. They are joined from
+    /// the segments kept for every square, without going over the squares.
+    pub fn take_changed_contours(&mut self) -> Option<Vec<Contour>> {
+        if !std::mem::take(&mut self.outlines_changed) {
+            return None;
+        }
+
+        Some(self.segments.chain())
+    }
+
+    /// Write the mesh to `mesh`, only updating the vertices and indices
+    /// patched since it was last written to the same `mesh`. The whole
+    /// mesh is written the first time, after it was built again, or if
+    /// `mesh` does not have the vertices and indices it was last given.
+    pub fn write_mesh(&mut self, mesh: &mut Mesh) {
+        let patched = match self.written.take() {
+            Some(written) => written.apply(&self.contour, mesh),
+            None => false,
+        };
+
+        if !patched {
+            *mesh = self.contour.clone().into_mesh();
+        }
+
+        self.written = Some(MeshWrites {
+            vertex_count: self.contour.positions.len(),
+            index_count: self.contour.indices.len(),
+            ..MeshWrites::default()
+        });
+    }
+
+    /// Forget the mesh, so it is built again by the next remesh.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// The changes to an `IncrementalMesh` since it was last written to a `Mesh`
+/// with `vertex_count` vertices and `index_count` indices.
+#[derive(Debug, Default, Clone)]
+struct MeshWrites {
+    vertex_count: usize,
+    index_count: usize,
+    /// The vertices that were moved, some of them more than once.
+    vertices: Vec<u32>,
+    /// The ranges of indices replaced, in order.
+    splices: Vec<(Range<usize>, Vec<u32>)>,
+}
+
+impl MeshWrites {
+    /// Patch `mesh` to the vertices and indices of `contour`. Returns
+    /// false if `mesh` is not the mesh the changes were made since.
+    fn apply(self, contour: &ContourMesh, mesh: &mut Mesh) -> bool {
+        let (old, new) = (self.vertex_count, contour.positions.len());
+        let written = |values: Option<&VertexAttributeValues>| match values {
+            Some(VertexAttributeValues::Float3(values)) => values.len() == old,
+            Some(VertexAttributeValues::Float2(values)) => values.len() == old,
+            _ => false,
+        };
+
+        let matches = written(mesh.attribute(Mesh::ATTRIBUTE_POSITION))
+            && written(mesh.attribute(Mesh::ATTRIBUTE_NORMAL))
+            && written(mesh.attribute(Mesh::ATTRIBUTE_UV_0))
+            && contour.uvs.len() == new
+            && contour.colors.is_empty()
+            && matches!(mesh.indices(), Some(Indices::U32(indices)) if indices.len() == self.index_count);
+
+        if !matches {
+            return false;
+        }
+
+        let moved = || {
+            self.vertices
+                .iter()
+                .map(|&v| v as usize)
+                .filter(|&v| v < old)
+        };
+
+        if let Some(VertexAttributeValues::Float3(positions)) =
+            mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION)
+        {
+            for v in moved() {
+                let [x, y] = contour.positions[v];
+                positions[v] = [x, y, 0.0];
+            }
+            positions.extend(contour.positions[old..].iter().map(|&[x, y]| [x, y, 0.0]));
+        }
+
+        if let Some(VertexAttributeValues::Float3(normals)) =
+            mesh.attribute_mut(Mesh::ATTRIBUTE_NORMAL)
+        {
+            normals.resize(new, [0.0, 0.0, 1.0]);
+        }
+
+        if let Some(VertexAttributeValues::Float2(uvs)) = mesh.attribute_mut(Mesh::ATTRIBUTE_UV_0) {
+            for v in moved() {
+                uvs[v] = contour.uvs[v];
+            }
+            uvs.extend_from_slice(&contour.uvs[old..]);
+        }
+
+        if let Some(Indices::U32(indices)) = mesh.indices_mut() {
+            for (replaced, spliced) in self.splices {
+                indices.splice(replaced, spliced);
+            }
+        }
+
+        true
+    }
+}
+
+/// The triangles built by a mesher, counter-clockwise.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ContourMesh {
+    pub positions: Vec<[f32; 2]>,
+    /// The cell every vertex belongs to: the cell at a corner,
+    /// or the solid cell at one end of an edge.
+    pub cells: Vec<CellPosition>,
+    pub uvs: Vec<[f32; 2]>,
+    /// The color of every vertex, empty if the mesh has no colors.
+    pub colors: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+}
+
+impl ContourMesh {
+    pub(crate) fn push_vertex(&mut self, point: Vec2, cell: CellPosition) -> u32 {
+        self.positions.push([point.x, point.y]);
+        self.cells.push(cell);
+        self.positions.len() as u32 - 1
+    }
+
+    /// Map texture coordinates onto every vertex of a mesh of `view`.
+    pub(crate) fn map_uvs<T>(&mut self, uvs: UvMapping, view: &ChunkView<T>, cell_size: CellSize) {
+        self.uvs = self
+            .positions
+            .iter()
+            .map(|&[x, y]| uvs.uv(view.size(), cell_size, view.position, Vec2::new(x, y)))
+            .collect();
+    }
+
+    /// Color every vertex after the cell it belongs to in `view`.
+    pub fn with_colors<'a, T: 'a, F>(mut self, view: impl Into<ChunkView<'a, T>>, color: F) -> Self
+    where
+        F: Fn(&T) -> [f32; 4],
+    {
+        let view = view.into();
+
+        self.colors = self
+            .cells
+            .iter()
+            .map(|&cell| view.get(cell).map(&color).unwrap_or([1.0; 4]))
+            .collect();
+
+        self
+    }
+
+    /// The triangles as `[a, b, c]` positions.
+    pub fn triangles(&self) -> impl Iterator<Item = [[f32; 2]; 3]> + '_ {
+        self.indices.chunks_exact(3).map(move |tri| {
+            [
+                self.positions[tri[0] as usize],
+                self.positions[tri[1] as usize],
+                self.positions[tri[2] as usize],
+            ]
+        })
+    }
+
+    /// The total area covered by the triangles.
+    pub fn area(&self) -> f32 {
+        self.triangles().map(triangle_area).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Add the triangles of `other`, without sharing any vertices.
+    pub fn append(&mut self, mut other: ContourMesh) {
+        let offset = self.positions.len() as u32;
+
+        // Colors are only kept if both meshes have them:
+        if self.colors.len() != self.positions.len() || other.colors.is_empty() {
+            self.colors.clear();
+            other.colors.clear();
+        }
+
+        self.positions.append(&mut other.positions);
+        self.cells.append(&mut other.cells);
+        self.uvs.append(&mut other.uvs);
+        self.colors.append(&mut other.colors);
+        self.indices
+            .extend(other.indices.into_iter().map(|index| index + offset));
+    }
+
+    /// A triangle list `Mesh` with normals along +Z, along with the
+    /// texture coordinates and colors of the vertices if it has them.
+    pub fn into_mesh(self) -> Mesh {
+        let positions: Vec<[f32; 3]> = self.positions.iter().map(|&[x, y]| [x, y, 0.0]).collect();
+        let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+
+        if self.uvs.len() == self.positions.len() {
+            mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        }
+
+        if !self.colors.is_empty() {
+            mesh.set_attribute(ATTRIBUTE_COLOR, self.colors);
+        }
+
+        mesh.set_indices(Some(Indices::U32(self.indices)));
+
+        mesh
+    }
+}
+
+/// The signed area of a triangle, positive if it is counter-clockwise.
+pub(crate) fn triangle_area([a, b, c]: [[f32; 2]; 3]) -> f32 {
+    ((b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{ChunkData, ChunkSize};
+
+    #[test]
+    fn every_case_is_counter_clockwise() {
+        let squares = MarchingSquares::new(0.5);
+        let size = CellSize::new(2, 2);
+
+        for case in 0..16 {
+            let mut contour = ContourMesh::default();
+            let mut cache = HashMap::new();
+            let corner = |bit: usize| ((case >> bit) & 1) as f32;
+            let corners = [corner(3), corner(2), corner(1), corner(0)];
+
+            let cell = CellPosition::new(0, 0);
+            squares.square(&mut contour, &mut cache, cell, corners, size);
+
+            for tri in contour.triangles() {
+                assert!(triangle_area(tri) > 0.0, "case {} is clockwise", case);
+            }
+        }
+    }
+
+    #[test]
+    fn solid_and_empty_chunks() {
+        let squares = MarchingSquares::new(0.5);
+        let cell_size = CellSize::new(8, 4);
+
+        let solid = ChunkData::new_with(ChunkSize::new(4, 4), 1.0);
+        let contour = squares.triangles(&solid, cell_size, |&cell| cell);
+        assert_eq!(3.0 * 3.0 * 8.0 * 4.0, contour.area());
+
+        let empty = ChunkData::new_with(ChunkSize::new(4, 4), 0.0);
+        assert!(squares
+            .triangles(&empty, cell_size, |&cell| cell)
+            .is_empty());
+    }
+
+    #[test]
+    fn single_solid_cell() {
+        let squares = MarchingSquares::new(0.5);
+        let mut data = ChunkData::new_with(ChunkSize::new(4, 4), false);
+        data.set((0, 0), true);
+
+        let contour = squares.triangles(&data, CellSize::new(2, 2), |&solid| solid as u8 as f32);
+
+        assert_eq!(4, contour.triangles().count());
+        assert_eq!(2.0, contour.area());
+        assert!(contour
+            .positions
+            .iter()
+            .all(|&[x, y]| x.abs() <= 1.0 && y.abs() <= 1.0));
+    }
+
+    #[test]
+    fn contour_points_lie_on_threshold() {
+        let threshold = 0.3;
+        let cell_size = CellSize::new(8, 4);
+        let field = |x: f32, y: f32| 0.11 * x - 0.07 * y;
+
+        let data = ChunkData::new_with_seed(ChunkSize::new(8, 8), |pos| {
+            field(pos.x as f32, pos.y as f32)
+        });
+        let contour = MarchingSquares::new(threshold).triangles(&data, cell_size, |&cell| cell);
+        assert!(!contour.is_empty());
+
+        for &[x, y] in &contour.positions {
+            let (cx, cy) = (x / 8.0, y / 4.0);
+            let density = field(cx, cy);
+
+            if cx.fract() == 0.0 && cy.fract() == 0.0 {
+                assert!(density > threshold);
+            } else {
+                assert!(
+                    (density - threshold).abs() < 1e-4,
+                    "{} at {}, {}",
+                    density,
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn edge_placements() {
+        assert_eq!(0.5, EdgePlacement::Midpoint.crossing(0.0, 1.0, 0.2));
+        assert_eq!(0.2, EdgePlacement::Interpolated.crossing(0.0, 1.0, 0.2));
+        assert_eq!(0.8, EdgePlacement::Interpolated.crossing(1.0, 0.0, 0.2));
+        assert_eq!(0.35, EdgePlacement::Smoothed.crossing(0.0, 1.0, 0.2));
+        assert_eq!(0.5, EdgePlacement::Interpolated.crossing(0.2, 0.2, 0.2));
+    }
+
+    #[test]
+    fn neighbours_close_the_gaps() {
+        let squares = MarchingSquares::new(0.5);
+        let cell_size = CellSize::new(2, 2);
+        let solid = ChunkData::new_with(ChunkSize::new(4, 4), 1.0);
+
+        let alone = squares.triangles(&solid, cell_size, |&cell| cell);
+        assert_eq!(3.0 * 3.0 * 4.0, alone.area());
+
+        let view = ChunkView::with_neighbours(&solid, |_| Some(&solid));
+        let contour = squares.triangles(view, cell_size, |&cell| cell);
+        assert_eq!(4.0 * 4.0 * 4.0, contour.area());
+
+        for &[x, y] in &contour.positions {
+            assert!((-4.0..=4.0).contains(&x) && (-4.0..=4.0).contains(&y));
+        }
+    }
+
+    #[test]
+    fn welded_vertices() {
+        let squares = MarchingSquares::new(0.5);
+        let cell_size = CellSize::new(2, 2);
+
+        let solid = ChunkData::new_with(ChunkSize::new(4, 4), 1.0);
+        let contour = squares.triangles(&solid, cell_size, |&cell| cell);
+        assert_eq!(16, contour.positions.len());
+        assert_eq!(3 * 3 * 6, contour.indices.len());
+
+        let mut data = ChunkData::new_with(ChunkSize::new(4, 4), 0.0);
+        data.set((0, 0), 1.0);
+        data.set((1, 0), 1.0);
+
+        let contour = squares.triangles(&data, cell_size, |&cell| cell);
+        let mut positions = contour.positions.clone();
+        positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        positions.dedup();
+
+        assert_eq!(positions.len(), contour.positions.len());
+        assert!(contour.indices.len() > 2 * contour.positions.len());
+    }
+
+    #[test]
+    fn vertex_attributes() {
+        let squares = MarchingSquares::new(0.5);
+        let mut data = ChunkData::new_with(ChunkSize::new(4, 4), 0.0f32);
+        data.set((0, 0), 0.8);
+        data.set((0, 1), 0.6);
+
+        let contour = squares
+            .triangles(&data, CellSize::new(2, 2), |&cell| cell)
+            .with_colors(&data, |&cell| [cell, 0.0, 0.0, 1.0]);
+
+        // Every vertex takes after one of the solid cells:
+        assert_eq!(contour.positions.len(), contour.colors.len());
+        assert!(contour
+            .colors
+            .iter()
+            .all(|color| color[0] == 0.8 || color[0] == 0.6));
+
+        let uv = contour.uvs[contour
+            .cells
+            .iter()
+            .position(|&c| c == (0, 0).into())
+            .unwrap()];
+        assert_eq!([0.5, 0.5], uv);
+
+        let vertices = contour.positions.len();
+        let mesh = contour.into_mesh();
+        for &attribute in &[
+            Mesh::ATTRIBUTE_NORMAL,
+            Mesh::ATTRIBUTE_UV_0,
+            ATTRIBUTE_COLOR,
+        ] {
+            assert_eq!(vertices, mesh.attribute(attribute).unwrap().len());
+        }
+    }
+
+    #[test]
+    fn materials_meet_without_gaps() {
+        let squares = MarchingSquares::new(0.5);
+        let size = CellSize::new(1, 1);
+
+        // Dirt on the left and stone on the right, less dense at the top:
+        let data = ChunkData::new_with_seed(ChunkSize::new(6, 6), |pos| {
+            let material = if pos.x < 0 { "dirt" } else { "stone" };
+            let density = match (pos.x.abs().max(pos.y.abs()), pos.y) {
+                (3, _) => 0.0,
+                (_, y) if y > 0 => 0.7,
+                _ => 1.0,
+            };
+
+            (material, density)
+        });
+
+        let single = squares.triangles(&data, size, |&(_, density)| density);
+        let materials = squares.materials(&data, size, |&cell| cell);
+
+        assert_eq!(
+            vec!["dirt", "stone"],
+            materials.iter().map(|&(m, _)| m).collect::<Vec<_>>()
+        );
+
+        let total: f32 = materials.iter().map(|(_, contour)| contour.area()).sum();
+        assert!((total - single.area()).abs() < 1e-4);
+
+        let mut joined = ContourMesh::default();
+        for (material, contour) in materials {
+            let contour = contour.with_colors(&data, |&(m, _)| {
+                let red = if m == material { 1.0 } else { 0.0 };
+                [red, 0.0, 0.0, 1.0]
+            });
+            joined.append(contour);
+        }
+
+        // Every vertex belongs to a cell of its own material:
+        assert_eq!(joined.positions.len(), joined.colors.len());
+        assert!(joined.colors.iter().all(|color| color[0] == 1.0));
+        assert!((joined.area() - single.area()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn remesh_matches_rebuild() {
+        let squares = MarchingSquares::new(0.5);
+        let cell_size = CellSize::new(2, 2);
+        let size = ChunkSize::new(8, 8);
+
+        // The triangles as sorted positions, so meshes can be compared:
+        let triangles = |contour: &ContourMesh| {
+            let mut triangles: Vec<Vec<(i32, i32)>> = contour
+                .triangles()
+                .map(|tri| {
+                    let mut tri: Vec<(i32, i32)> = tri
+                        .iter()
+                        .map(|&[x, y]| ((x * 1e4).round() as i32, (y * 1e4).round() as i32))
+                        .collect();
+                    tri.sort_unstable();
+                    tri
+                })
+                .collect();
+            triangles.sort();
+            triangles
+        };
+
+        let mut data = ChunkData::new_with_seed(size, |pos| {
+            0.2 + 0.1 * pos.x as f32 - 0.05 * (pos.y * pos.y) as f32
+        });
+        let right = ChunkData::new_with(size, 0.8);
+        fn with_right<'a>(
+            data: &'a ChunkData<f32>,
+            right: &'a ChunkData<f32>,
+        ) -> ChunkView<'a, f32> {
+            let mut view = ChunkView::new(data);
+            view.set_neighbour((1, 0), Some(right));
+            view
+        }
+
+        let mut mesh = IncrementalMesh::default();
+        let dirty = data.take_dirty();
+        squares.remesh(
+            &mut mesh,
+            with_right(&data, &right),
+            cell_size,
+            |&d| d,
+            dirty,
+        );
+        let built = mesh.contour().indices.len();
+
+        let mut written = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.write_mesh(&mut written);
+        let mut contours = mesh.take_changed_contours().unwrap();
+
+        // Dig a hole, fill part of it again, and edit the border with the neighbour:
+        let edits = [
+            CellRect::new((-2, -1).into(), (0, 1).into()),
+            CellRect::cell((-1, 0).into()),
+            CellRect::new((3, -4).into(), (3, 3).into()),
+            CellRect::cell((-4, 3).into()),
+        ];
+
+        for (i, &edit) in edits.iter().enumerate() {
+            let value = if i % 2 == 0 { 1.0 } else { 0.0 };
+            data.fill_rect(edit, value);
+
+            let dirty = data.take_dirty();
+            assert!(dirty.is_some());
+            squares.remesh(
+                &mut mesh,
+                with_right(&data, &right),
+                cell_size,
+                |&d| d,
+                dirty,
+            );
+
+            let rebuilt = squares.triangles(with_right(&data, &right), cell_size, |&d| d);
+            assert_eq!(triangles(&rebuilt), triangles(mesh.contour()));
+            assert_eq!(mesh.contour().positions.len(), mesh.contour().uvs.len());
+
+            // The mesh written in place matches the one written whole:
+            mesh.write_mesh(&mut written);
+            let contour = mesh.contour();
+            match written.attribute(Mesh::ATTRIBUTE_POSITION) {
+                Some(VertexAttributeValues::Float3(positions)) => {
+                    let positions: Vec<[f32; 2]> =
+                        positions.iter().map(|&[x, y, _]| [x, y]).collect();
+                    assert_eq!(contour.positions, positions);
+                }
+                _ => panic!(),
+            }
+            match written.attribute(Mesh::ATTRIBUTE_UV_0) {
+                Some(VertexAttributeValues::Float2(uvs)) => assert_eq!(&contour.uvs, uvs),
+                _ => panic!(),
+            }
+            match written.indices() {
+                Some(Indices::U32(indices)) => assert_eq!(&contour.indices, indices),
+                _ => panic!(),
+            }
+
+            // The contours are joined from the segments kept for every square,
+            // when any of them changed:
+            if let Some(changed) = mesh.take_changed_contours() {
+                contours = changed;
+            }
+            let rebuilt = squares.contours(with_right(&data, &right), cell_size, |&d| d);
+            assert_eq!(rebuilt, contours);
+        }
+
+        // Cells set to the same value leave the contours as they are:
+        data.fill_rect(edits[2], 1.0);
+        let dirty = data.take_dirty();
+        squares.remesh(
+            &mut mesh,
+            with_right(&data, &right),
+            cell_size,
+            |&d| d,
+            dirty,
+        );
+        assert_eq!(None, mesh.take_changed_contours());
+
+        assert_ne!(built, mesh.contour().indices.len());
+
+        // Every vertex in use stays welded, and has the uv of its position:
+        let contour = mesh.contour();
+        let mut positions: Vec<[f32; 2]> = contour
+            .indices
+            .iter()
+            .map(|&i| contour.positions[i as usize])
+            .collect();
+        positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        positions.dedup();
+
+        let mut used = contour.indices.clone();
+        used.sort_unstable();
+        used.dedup();
+        assert_eq!(positions.len(), used.len());
+
+        for (&[x, y], &uv) in contour.positions.iter().zip(&contour.uvs) {
+            let expected =
+                squares
+                    .uvs
+                    .uv(size, cell_size, ChunkPosition::new(0, 0), Vec2::new(x, y));
+            assert_eq!(expected, uv);
+        }
+
+        // A chunk with other neighbours is built again, even if it has a dirty rectangle:
+        data.set((0, 0), 1.0);
+        let dirty = data.take_dirty();
+        squares.remesh(&mut mesh, &data, cell_size, |&d| d, dirty);
+        let alone = squares.triangles(&data, cell_size, |&d| d);
+        assert_eq!(triangles(&alone), triangles(mesh.contour()));
+    }
+}
@@ -0,0 +1,76 @@
+use bevy::prelude::Vec2;
+
+use crate::{CellSize, ChunkPosition, ChunkSize};
+
+/// The vertex attribute holding the `[f32; 4]` color of every vertex of a
+/// chunk mesh, when the mesher adds colors. Bevy's `StandardMaterial`
+/// ignores it, custom shaders can read it as `Vertex_Color`.
+pub const ATTRIBUTE_COLOR: &str = "Vertex_Color";
+
+/// How textures are mapped onto chunk meshes. V goes down
+/// the texture as the mesh goes down the world.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum UvMapping {
+    /// The texture repeated once per cell, aligned with the edges of the cells.
+    #[default]
+    Cell,
+    /// The texture repeated every `tile` world units, lining up across chunks.
+    World { tile: Vec2 },
+}
+
+impl UvMapping {
+    /// The texture coordinates of `point`, relative to the center of the chunk at `chunk`.
+    pub fn uv(
+        &self,
+        chunk_size: ChunkSize,
+        cell_size: CellSize,
+        chunk: ChunkPosition,
+        point: Vec2,
+    ) -> [f32; 2] {
+        match *self {
+            UvMapping::Cell => {
+                // Chunks are a whole number of cells apart, so local
+                // positions line up with the cells of every chunk:
+                let uv = point / cell_size.as_vec2();
+                [uv.x + 0.5, 0.5 - uv.y]
+            }
+            UvMapping::World { tile } => {
+                // Wrap the chunk's position first, so far away chunks keep their precision:
+                let world = chunk.to_world(chunk_size, cell_size);
+                let offset = Vec2::new(world.x.rem_euclid(tile.x), world.y.rem_euclid(tile.y));
+                let uv = (point + offset) / tile;
+                [uv.x, -uv.y]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uvs_line_up_across_chunks() {
+        let chunk_size = ChunkSize::new(4, 4);
+        let cell_size = CellSize::new(2, 2);
+        let left = ChunkPosition::new(0, 0);
+        let right = ChunkPosition::new(1, 0);
+
+        // The right edge of the left chunk is the left edge of the right chunk:
+        let (a, b) = (Vec2::new(4.0, 1.0), Vec2::new(-4.0, 1.0));
+
+        let cell = UvMapping::Cell;
+        assert_eq!([2.5, 0.0], cell.uv(chunk_size, cell_size, left, a));
+        assert_eq!([-1.5, 0.0], cell.uv(chunk_size, cell_size, right, b));
+
+        let world = UvMapping::World {
+            tile: Vec2::new(3.0, 3.0),
+        };
+        let (a, b) = (
+            world.uv(chunk_size, cell_size, left, a),
+            world.uv(chunk_size, cell_size, right, b),
+        );
+        assert!((a[0] - b[0]).fract().abs() < 1e-5);
+        assert_eq!(a[1], b[1]);
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::{max_level, CellPosition, CellRect, ChunkData, ChunkGeneration, ChunkSize};
+
+impl CellPosition {
+    /// The position of the cell covering this one in a chunk downsampled
+    /// to `level`, see `ChunkData::downsample`.
+    pub fn to_level(self, level: u8) -> Self {
+        let step = 1 << level;
+
+        // Blocks start at their top left cell, x grows right and y grows up:
+        CellPosition::new(self.x.div_euclid(step), -(-self.y).div_euclid(step))
+    }
+
+    /// The cells of the original chunk covered by this cell of a chunk
+    /// downsampled to `level`.
+    pub fn from_level(self, level: u8) -> CellRect {
+        let step = 1 << level;
+        let top_left = CellPosition::new(self.x * step, self.y * step);
+
+        CellRect::new(
+            top_left,
+            CellPosition::new(top_left.x + step - 1, top_left.y - step + 1),
+        )
+    }
+}
+
+impl<T> ChunkData<T> {
+    /// The chunk with every block of `2^level` by `2^level` cells reduced
+    /// to a single cell by `reduce`, which gets the cells of the block
+    /// row by row from the top left. Cells of the downsampled chunk are
+    /// addressed as usual from its center, `CellPosition::to_level` and
+    /// `CellPosition::from_level` convert between both chunks.
+    ///
+    /// Both sides of the chunk must still have an even number of cells
+    /// once downsampled, so `level` is at most `max_level(size)`.
+    pub fn downsample<R, F>(&self, level: u8, mut reduce: F) -> ChunkData<R>
+    where
+        F: FnMut(&[&T]) -> R,
+    {
+        assert!(
+            level <= max_level(self.size),
+            "chunks of {:?} cannot be downsampled to level {}",
+            self.size,
+            level
+        );
+
+        let step = 1 << level;
+        let size = ChunkSize::new(self.size.width / step, self.size.height / step);
+
+        ChunkData::new_with_seed(size, |pos| reduce(&self.block(pos, level)))
+    }
+
+    /// The cells covered by the cell at `pos` of the chunk downsampled to `level`.
+    fn block(&self, pos: CellPosition, level: u8) -> Vec<&T> {
+        pos.from_level(level)
+            .iter()
+            .filter_map(|pos| self.get(pos))
+            .collect()
+    }
+}
+
+/// The average of the cells.
+pub fn average_cells(cells: &[&f32]) -> f32 {
+    cells.iter().copied().sum::<f32>() / cells.len() as f32
+}
+
+/// The largest of the cells, the first one if several are as large.
+pub fn max_cells<T: PartialOrd + Clone>(cells: &[&T]) -> T {
+    let mut max = cells[0];
+
+    for &cell in &cells[1..] {
+        if cell > max {
+            max = cell;
+        }
+    }
+
+    max.clone()
+}
+
+/// The most common of the cells, the first one if several are as common.
+pub fn majority_cells<T: Eq + Hash + Clone>(cells: &[&T]) -> T {
+    let mut counts: HashMap<&T, usize> = HashMap::new();
+
+    for &cell in cells {
+        *counts.entry(cell).or_insert(0) += 1;
+    }
+
+    let mut majority = cells[0];
+
+    for &cell in cells {
+        if counts[cell] > counts[majority] {
+            majority = cell;
+        }
+    }
+
+    majority.clone()
+}
+
+/// Reduces a block of cells of a chunk to a single cell.
+pub type CellReduce<T, R> = Box<dyn Fn(&[&T]) -> R + Send + Sync>;
+
+/// The downsampled levels of a chunk, built when they are first used and
+/// updated where the chunk changed since.
+///
+/// Changes are found by comparing the `ChunkData::generation` of the
+/// chunk, so the dirty rectangle is left for the mesher to take.
+pub struct ChunkMips<T, R> {
+    reduce: CellReduce<T, R>,
+    levels: Vec<Option<ChunkData<R>>>,
+    generation: ChunkGeneration,
+    dirty: Option<CellRect>,
+}
+
+impl<T, R> ChunkMips<T, R> {
+    pub fn new<F>(reduce: F) -> Self
+    where
+        F: Fn(&[&T]) -> R + Send + Sync + 'static,
+    {
+        Self {
+            reduce: Box::new(reduce),
+            levels: Vec::new(),
+            generation: ChunkGeneration::default(),
+            dirty: None,
+        }
+    }
+
+    /// Mark the cells in `rect` of the original chunk as changed,
+    /// for cells written to `ChunkData::data` directly.
+    pub fn invalidate(&mut self, rect: CellRect) {
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => dirty.union(rect),
+            None => rect,
+        });
+    }
+
+    /// Forget every level, so they are all built again.
+    pub fn clear(&mut self) {
+        self.levels.clear();
+        self.dirty = None;
+    }
+
+    /// The level already built, without the changes to the chunk since.
+    pub fn cached(&self, level: u8) -> Option<&ChunkData<R>> {
+        self.levels.get(level as usize)?.as_ref()
+    }
+
+    /// `data` downsampled to `level`, see `ChunkData::downsample`.
+    pub fn level(&mut self, data: &ChunkData<T>, level: u8) -> &ChunkData<R> {
+        if let Some(changed) = data.changed_since(self.generation) {
+            self.invalidate(changed);
+        }
+        self.generation = data.generation();
+
+        if let Some(dirty) = self.dirty.take() {
+            for (level, mip) in self.levels.iter_mut().enumerate() {
+                let mip = match mip {
+                    Some(mip) => mip,
+                    None => continue,
+                };
+
+                let level = level as u8;
+                let rect = CellRect::new(dirty.min.to_level(level), dirty.max.to_level(level));
+
+                for pos in rect.iter() {
+                    if let Some(cell) = mip.get_mut(pos) {
+                        *cell = (self.reduce)(&data.block(pos, level));
+                    }
+                }
+            }
+        }
+
+        let index = level as usize;
+        if self.levels.len() <= index {
+            self.levels.resize_with(index + 1, || None);
+        }
+
+        let reduce = &self.reduce;
+        self.levels[index].get_or_insert_with(|| data.downsample(level, reduce))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn reduced_levels() {
+        let size = ChunkSize::new(8, 4);
+        let data = ChunkData::new_with_seed(size, |pos| (pos.x + pos.y) as f32);
+
+        let averages = data.downsample(1, average_cells);
+        assert_eq!(ChunkSize::new(4, 2), averages.size);
+        // The top left block covers (-4, 2), (-3, 2), (-4, 1) and (-3, 1):
+        assert_eq!(Some(&-2.0), averages.get((-2, 1)));
+
+        let maxes = data.downsample(1, max_cells);
+        assert_eq!(Some(&-1.0), maxes.get((-2, 1)));
+
+        let solid = ChunkData::new_with_seed(size, |pos| pos.y > 0 || pos.x == 0);
+        let majorities = solid.downsample(1, majority_cells);
+        assert_eq!(Some(&true), majorities.get((0, 1)));
+        assert_eq!(Some(&false), majorities.get((-1, 0)));
+        // A tie, won by the top left cell:
+        assert_eq!(Some(&true), majorities.get((0, 0)));
+
+        for pos in data.bounds().iter() {
+            assert!(pos.to_level(1).from_level(1).contains(pos));
+        }
+
+        assert_eq!(
+            CellPosition::new(-2, 1),
+            CellPosition::new(-3, 1).to_level(1)
+        );
+        assert_eq!(
+            CellPosition::new(1, 0),
+            CellPosition::new(3, -1).to_level(1)
+        );
+    }
+
+    #[test]
+    fn invalidated_blocks() {
+        let reduced = Arc::new(AtomicUsize::new(0));
+        let counter = reduced.clone();
+
+        let mut data = ChunkData::new_with(ChunkSize::new(8, 8), 0.0f32);
+        let mut mips = ChunkMips::new(move |cells: &[&f32]| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            average_cells(cells)
+        });
+
+        assert_eq!(Some(&0.0), mips.level(&data, 1).get((0, 0)));
+        mips.level(&data, 2);
+        assert_eq!(16 + 4, reduced.load(Ordering::Relaxed));
+
+        data.set((1, -1), 16.0);
+        reduced.store(0, Ordering::Relaxed);
+
+        // Only the block around the changed cell is reduced again, at both
+        // levels, and the dirty rectangle is left to the mesher:
+        assert_eq!(Some(&4.0), mips.level(&data, 1).get((0, 0)));
+        assert_eq!(Some(&1.0), mips.cached(2).unwrap().get((0, 0)));
+        assert_eq!(2, reduced.load(Ordering::Relaxed));
+        assert!(data.is_dirty());
+
+        // Unchanged chunks are not reduced again, even once the mesher took the rectangle:
+        data.take_dirty();
+        mips.level(&data, 2);
+        assert_eq!(2, reduced.load(Ordering::Relaxed));
+
+        // A chunk loaded again in its place is reduced again:
+        let data = ChunkData::new_with(ChunkSize::new(8, 8), 1.0f32);
+        assert_eq!(Some(&1.0), mips.level(&data, 1).get((0, 0)));
+        assert_eq!(2 + 16 + 4, reduced.load(Ordering::Relaxed));
+    }
+}
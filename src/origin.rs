@@ -0,0 +1,184 @@
+use bevy::prelude::*;
+
+use crate::{
+    CellSize, ChunkCollider, ChunkColliders, ChunkGrid, ChunkInfo, ChunkLayer, ChunkPosition,
+    ChunkSize,
+};
+
+/// The chunk at the center of the local frame that transforms are in.
+///
+/// `ChunkPosition`s are absolute, but `Transform`s are relative to the
+/// center of the `origin` chunk. When `threshold` is set the origin is
+/// moved onto the `FloatingOriginAnchor` once it gets more than
+/// `threshold` chunks away, keeping `f32` positions small far from
+/// the center of the world.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct FloatingOrigin {
+    pub origin: ChunkPosition,
+    pub threshold: Option<u32>,
+}
+
+impl FloatingOrigin {
+    /// A floating origin that recentres once the anchor
+    /// is more than `threshold` chunks away.
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            origin: ChunkPosition::new(0, 0),
+            threshold: Some(threshold),
+        }
+    }
+
+    /// The origin never moves, local and absolute coordinates are the same.
+    pub fn fixed() -> Self {
+        Self {
+            origin: ChunkPosition::new(0, 0),
+            threshold: None,
+        }
+    }
+
+    /// The center of the chunk at `position` in the local frame.
+    pub fn chunk_to_local(
+        &self,
+        chunk_size: ChunkSize,
+        cell_size: CellSize,
+        position: ChunkPosition,
+    ) -> Vec2 {
+        (position - self.origin).to_world(chunk_size, cell_size)
+    }
+
+    /// Convert a point `offset` from the center of the chunk
+    /// at `position` into the local frame.
+    pub fn to_local(
+        &self,
+        chunk_size: ChunkSize,
+        cell_size: CellSize,
+        position: ChunkPosition,
+        offset: Vec2,
+    ) -> Vec2 {
+        self.chunk_to_local(chunk_size, cell_size, position) + offset
+    }
+
+    /// Convert a point in the local frame into the absolute chunk it is
+    /// in and its offset from the center of that chunk.
+    pub fn to_absolute(
+        &self,
+        chunk_size: ChunkSize,
+        cell_size: CellSize,
+        local: Vec2,
+    ) -> (ChunkPosition, Vec2) {
+        let relative = ChunkPosition::from_world(chunk_size, cell_size, local);
+        let offset = local - relative.to_world(chunk_size, cell_size);
+
+        (self.origin + relative, offset)
+    }
+
+    /// Whether the origin should move to `anchor`.
+    pub fn needs_recentre(&self, anchor: ChunkPosition) -> bool {
+        match self.threshold {
+            Some(threshold) => self.origin.distance(anchor) > threshold,
+            None => false,
+        }
+    }
+}
+
+impl Default for FloatingOrigin {
+    fn default() -> Self {
+        Self::fixed()
+    }
+}
+
+/// The entity the `FloatingOrigin` follows, usually the player
+/// or the camera. Needs an up to date `ChunkPosition`.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct FloatingOriginAnchor;
+
+/// Sent when the origin moves. `translation` was added to every
+/// root `Transform`, anything keeping positions outside of
+/// transforms (like physics bodies) must be shifted by it too.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OriginShifted {
+    pub from: ChunkPosition,
+    pub to: ChunkPosition,
+    pub translation: Vec2,
+}
+
+/// Move the origin onto the anchor once it strays too far,
+/// shifting every root entity into the new frame.
+pub fn recentre_floating_origin(
+    grid: Res<ChunkGrid>,
+    mut origin: ResMut<FloatingOrigin>,
+    mut shifted: ResMut<Events<OriginShifted>>,
+    anchors: Query<&ChunkPosition, With<FloatingOriginAnchor>>,
+    mut roots: Query<&mut Transform, Without<Parent>>,
+) {
+    let anchor = match anchors.iter().next() {
+        Some(&anchor) if origin.needs_recentre(anchor) => anchor,
+        _ => return,
+    };
+
+    let translation = (origin.origin - anchor).to_world(grid.chunk_size, grid.cell_size);
+
+    for mut transform in roots.iter_mut() {
+        transform.translation += translation.extend(0.0);
+    }
+
+    let from = origin.origin;
+    origin.origin = anchor;
+
+    shifted.send(OriginShifted {
+        from,
+        to: anchor,
+        translation,
+    });
+}
+
+/// Move the colliders of the chunks of the layer `L`
+/// into the frame of the new origin.
+pub fn shift_chunk_colliders<L: ChunkLayer>(
+    commands: &mut Commands,
+    info: Res<ChunkInfo<L>>,
+    colliders: Res<ChunkColliders<L>>,
+    origin: Res<FloatingOrigin>,
+    mut reader: Local<EventReader<OriginShifted>>,
+    shifted: Res<Events<OriginShifted>>,
+    chunks: Query<(Entity, &ChunkPosition), (With<ChunkCollider>, With<L>)>,
+) {
+    if reader.iter(&shifted).last().is_none() {
+        return;
+    }
+
+    for (entity, &position) in chunks.iter() {
+        let local = origin.chunk_to_local(info.chunk_size, info.cell_size, position);
+        colliders.backend.set_translation(commands, entity, local);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_and_absolute() {
+        let chunk_size = ChunkSize::new(10, 10);
+        let cell_size = CellSize::new(1, 1);
+
+        let mut origin = FloatingOrigin::new(2);
+        assert!(!origin.needs_recentre((2, -2).into()));
+        assert!(origin.needs_recentre((3, 0).into()));
+
+        origin.origin = ChunkPosition::new(1000, -1000);
+
+        let offset = Vec2::new(2.0, -3.0);
+        let local = origin.to_local(chunk_size, cell_size, (1001, -1000).into(), offset);
+        assert_eq!(Vec2::new(12.0, -3.0), local);
+
+        assert_eq!(
+            (ChunkPosition::new(1001, -1000), offset),
+            origin.to_absolute(chunk_size, cell_size, local)
+        );
+        assert_eq!(
+            (ChunkPosition::new(1000, -1000), Vec2::zero()),
+            origin.to_absolute(chunk_size, cell_size, Vec2::zero())
+        );
+    }
+}
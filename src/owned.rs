@@ -0,0 +1,330 @@
+use bevy::ecs::Component;
+use bevy::prelude::*;
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+
+use crate::{
+    ChunkInfo, ChunkLayer, ChunkMap, ChunkPosition, ChunkTracked, DespawnChunk, FloatingOrigin,
+};
+
+/// Parents an entity to the chunk of the layer `L` it is in, so it is
+/// despawned along with the chunk instead of being leaked.
+///
+/// The entity is moved to another chunk when its `ChunkPosition` changes,
+/// add `ChunkTracked` to keep it up to date. When its chunk unloads the
+/// entity is saved to `ChunkOwnedStore` and respawned once the chunk loads again.
+pub struct ChunkOwned<L>(PhantomData<fn() -> L>);
+
+impl<L> Default for ChunkOwned<L> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<L> Clone for ChunkOwned<L> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<L> Copy for ChunkOwned<L> {}
+
+impl<L> Debug for ChunkOwned<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ChunkOwned")
+    }
+}
+
+type SavedComponent = Box<dyn Any + Send + Sync>;
+
+struct ComponentSaver {
+    save: fn(&World, Entity) -> Option<SavedComponent>,
+    restore: fn(&mut World, Entity, SavedComponent),
+}
+
+fn save_component<C: Component + Clone>(world: &World, entity: Entity) -> Option<SavedComponent> {
+    let component = world.get::<C>(entity).ok()?;
+
+    Some(Box::new(component.clone()))
+}
+
+fn restore_component<C: Component + Clone>(
+    world: &mut World,
+    entity: Entity,
+    component: SavedComponent,
+) {
+    if let Ok(component) = component.downcast::<C>() {
+        let _ = world.insert_one(entity, *component);
+    }
+}
+
+/// The components saved along with `ChunkOwned` entities. Components
+/// that are not registered here are lost when the chunk unloads.
+///
+/// `Transform`, `ChunkPosition` and `ChunkTracked` are registered by
+/// default, `ChunkOwned` is added back by the layer that saved the entity.
+pub struct ChunkOwnedComponents {
+    savers: Vec<ComponentSaver>,
+}
+
+impl ChunkOwnedComponents {
+    /// An empty registry, not even saving the `Transform` of entities.
+    pub fn empty() -> Self {
+        Self { savers: Vec::new() }
+    }
+
+    pub fn register<C: Component + Clone>(&mut self) {
+        self.savers.push(ComponentSaver {
+            save: save_component::<C>,
+            restore: restore_component::<C>,
+        });
+    }
+
+    pub fn with<C: Component + Clone>(mut self) -> Self {
+        self.register::<C>();
+        self
+    }
+
+    /// Copy every registered component of `entity`.
+    pub fn save(&self, world: &World, entity: Entity) -> SavedEntity {
+        let components = self
+            .savers
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, saver)| (saver.save)(world, entity).map(|c| (idx, c)))
+            .collect();
+
+        SavedEntity { components }
+    }
+
+    /// Insert the saved components into `entity`.
+    pub fn restore(&self, world: &mut World, entity: Entity, saved: SavedEntity) {
+        for (idx, component) in saved.components {
+            (self.savers[idx].restore)(world, entity, component);
+        }
+    }
+}
+
+impl Default for ChunkOwnedComponents {
+    fn default() -> Self {
+        Self::empty()
+            .with::<Transform>()
+            .with::<ChunkPosition>()
+            .with::<ChunkTracked>()
+    }
+}
+
+/// The registered components of an unloaded `ChunkOwned` entity.
+/// The `Transform` is relative to the chunk the entity was in.
+pub struct SavedEntity {
+    components: Vec<(usize, SavedComponent)>,
+}
+
+impl SavedEntity {
+    pub fn get<C: Component>(&self) -> Option<&C> {
+        self.components
+            .iter()
+            .find_map(|(_, component)| component.downcast_ref::<C>())
+    }
+}
+
+/// `ChunkOwned` entities of unloaded chunks of the layer `L`,
+/// waiting for their chunk to be loaded again.
+///
+/// Persistence can take entities out of the store to write
+/// them to disk, and put them back before their chunk loads.
+pub struct ChunkOwnedStore<L>(
+    pub HashMap<ChunkPosition, Vec<SavedEntity>>,
+    PhantomData<fn() -> L>,
+);
+
+impl<L> Default for ChunkOwnedStore<L> {
+    fn default() -> Self {
+        Self(HashMap::new(), PhantomData)
+    }
+}
+
+impl<L> ChunkOwnedStore<L> {
+    pub fn insert(&mut self, position: ChunkPosition, saved: SavedEntity) {
+        self.0.entry(position).or_default().push(saved);
+    }
+
+    pub fn take(&mut self, position: ChunkPosition) -> Vec<SavedEntity> {
+        self.0.remove(&position).unwrap_or_default()
+    }
+}
+
+/// Parent every `ChunkOwned` entity to the chunk at its `ChunkPosition`.
+/// Entities in chunks that are not loaded are left without a parent.
+/// Children of unloading chunks are left in place, to be saved and
+/// despawned along with their chunk.
+///
+/// Chunks only have a translation, so the world translation of an
+/// entity is its chunk's translation plus its own.
+pub fn parent_owned_entities<L: ChunkLayer>(
+    commands: &mut Commands,
+    info: Res<ChunkInfo<L>>,
+    origin: Res<FloatingOrigin>,
+    map: Res<ChunkMap<L>>,
+    chunks: Query<&ChunkPosition, (With<Children>, With<L>)>,
+    unloading: Query<Entity, (With<DespawnChunk>, With<L>)>,
+    mut owned: Query<
+        (Entity, &ChunkPosition, &mut Transform, Option<&Parent>),
+        With<ChunkOwned<L>>,
+    >,
+) {
+    for (entity, position, mut transform, parent) in owned.iter_mut() {
+        let chunk = map.0.get(position).copied();
+        let parent = parent.map(|parent| parent.0);
+
+        if parent == chunk || matches!(parent, Some(parent) if unloading.get(parent).is_ok()) {
+            continue;
+        }
+
+        let parent_world = match parent.and_then(|parent| chunks.get(parent).ok()) {
+            Some(&parent) => origin.chunk_to_local(info.chunk_size, info.cell_size, parent),
+            None => Vec2::zero(),
+        };
+        let world = transform.translation + parent_world.extend(0.0);
+
+        match chunk {
+            Some(chunk) => {
+                let chunk_world = origin.chunk_to_local(info.chunk_size, info.cell_size, *position);
+
+                transform.translation = world - chunk_world.extend(0.0);
+                commands.push_children(chunk, &[entity]);
+            }
+            None => {
+                transform.translation = world;
+                commands.remove_one::<Parent>(entity);
+            }
+        }
+    }
+}
+
+/// Save the `ChunkOwned` children of chunks about to be despawned.
+pub fn save_owned_entities<L: ChunkLayer>(world: &mut World, resources: &mut Resources) {
+    let registry = resources.get::<ChunkOwnedComponents>().unwrap();
+    let mut store = resources.get_mut::<ChunkOwnedStore<L>>().unwrap();
+
+    let unloading: Vec<(ChunkPosition, Entity)> = world
+        .query_filtered::<(&ChunkPosition, Entity), (With<DespawnChunk>, With<L>)>()
+        .map(|(&position, entity)| (position, entity))
+        .collect();
+
+    for (position, chunk) in unloading {
+        let children: Vec<Entity> = match world.get::<Children>(chunk) {
+            Ok(children) => children.iter().copied().collect(),
+            Err(_) => continue,
+        };
+
+        for child in children {
+            if world.get::<ChunkOwned<L>>(child).is_ok() {
+                store.insert(position, registry.save(world, child));
+            }
+        }
+    }
+}
+
+/// Respawn the saved entities of chunks that have been loaded again.
+pub fn restore_owned_entities<L: ChunkLayer>(world: &mut World, resources: &mut Resources) {
+    let registry = resources.get::<ChunkOwnedComponents>().unwrap();
+    let map = resources.get::<ChunkMap<L>>().unwrap();
+    let mut store = resources.get_mut::<ChunkOwnedStore<L>>().unwrap();
+
+    let loaded: Vec<(ChunkPosition, Entity)> = store
+        .0
+        .keys()
+        .filter_map(|position| map.0.get(position).map(|&chunk| (*position, chunk)))
+        .collect();
+
+    for (position, chunk) in loaded {
+        for saved in store.take(position) {
+            let entity = world.spawn((
+                GlobalTransform::default(),
+                Parent(chunk),
+                ChunkOwned::<L>::default(),
+            ));
+            registry.restore(world, entity, saved);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bevy::ecs::{Stage, SystemStage};
+
+    use crate::{CellSize, ChunkSize};
+
+    #[derive(Default)]
+    struct Terrain;
+
+    impl ChunkLayer for Terrain {
+        type Cell = u8;
+    }
+
+    #[test]
+    fn save_children_of_unloaded_chunks() {
+        let mut world = World::default();
+        let mut resources = Resources::default();
+
+        resources.insert(ChunkInfo::<Terrain>::new(
+            ChunkSize::new(4, 4),
+            CellSize::new(1, 1),
+        ));
+        resources.insert(FloatingOrigin::default());
+        resources.insert(ChunkOwnedComponents::default());
+        resources.insert(ChunkOwnedStore::<Terrain>::default());
+
+        let position = ChunkPosition::new(0, 0);
+        let chunk = world.spawn((
+            position,
+            Terrain,
+            Transform::default(),
+            GlobalTransform::default(),
+        ));
+        let owned = world.spawn((
+            position,
+            Transform::from_translation(Vec3::new(1.0, 2.0, 0.0)),
+            GlobalTransform::default(),
+            ChunkOwned::<Terrain>::default(),
+        ));
+
+        let mut map = ChunkMap::<Terrain>::default();
+        map.0.insert(position, chunk);
+        resources.insert(map);
+
+        let mut parent = SystemStage::serial();
+        parent.add_system(parent_owned_entities::<Terrain>.system());
+        parent.run(&mut world, &mut resources);
+        assert_eq!(chunk, world.get::<Parent>(owned).unwrap().0);
+
+        // Unload the chunk the way the plugin does, then mesh and save:
+        resources
+            .get_mut::<ChunkMap<Terrain>>()
+            .unwrap()
+            .0
+            .remove(&position);
+        world.insert_one(chunk, DespawnChunk).unwrap();
+
+        parent.run(&mut world, &mut resources);
+        assert_eq!(chunk, world.get::<Parent>(owned).unwrap().0);
+
+        let mut save = SystemStage::serial();
+        save.add_system(save_owned_entities::<Terrain>.system());
+        save.run(&mut world, &mut resources);
+
+        let store = resources.get::<ChunkOwnedStore<Terrain>>().unwrap();
+        let saved = &store.0[&position];
+        assert_eq!(1, saved.len());
+        assert_eq!(
+            Vec3::new(1.0, 2.0, 0.0),
+            saved[0].get::<Transform>().unwrap().translation
+        );
+    }
+}
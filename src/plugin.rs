@@ -0,0 +1,651 @@
+use bevy::prelude::*;
+use bevy::render::pipeline::PrimitiveTopology;
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use crate::{
+    apply_cell_edits, index_chunk_entities, insert_chunk_positions, parent_owned_entities,
+    recentre_floating_origin, restore_owned_entities, save_owned_entities, shift_chunk_colliders,
+    track_chunk_positions, CellEdit, CellRect, CellSize, Chunk, ChunkBudget, ChunkCollider,
+    ChunkColliderShape, ChunkColliders, ChunkData, ChunkEntities, ChunkGrid, ChunkInfo, ChunkLayer,
+    ChunkLoader, ChunkLoaderVelocity, ChunkLods, ChunkOwnedComponents, ChunkOwnedStore,
+    ChunkPosition, ChunkQueue, ChunkSize, ChunkTickets, ChunkView, ColliderDecomposition, Contour,
+    FloatingOrigin, IncrementalMesh, MarchingSquares, OriginShifted,
+};
+
+pub mod stage {
+    /// `ChunkOwned` entities of unloading chunks are saved
+    /// and the ones of reloaded chunks are restored.
+    pub const CHUNK_SAVE: &str = "chunk_save";
+    /// `CellEdit` events are applied to the chunks they change.
+    pub const CHUNK_EDIT: &str = "chunk_edit";
+    /// Chunks are queued, spawned and marked for despawn.
+    pub const CHUNK_LOAD: &str = "chunk_load";
+    /// Meshes of newly spawned chunks are built and uploaded.
+    pub const CHUNK_MESH: &str = "chunk_mesh";
+}
+
+/// The entity of every spawned chunk of the layer `L`.
+pub struct ChunkMap<L>(pub HashMap<ChunkPosition, Entity>, PhantomData<fn() -> L>);
+
+impl<L> Default for ChunkMap<L> {
+    fn default() -> Self {
+        Self(HashMap::new(), PhantomData)
+    }
+}
+
+/// Chunks of the layer `L` waiting to be spawned, meshed or despawned.
+pub struct ChunkQueues<L> {
+    pub load: ChunkQueue,
+    pub mesh: ChunkQueue,
+    pub unload: ChunkQueue,
+    marker: PhantomData<fn() -> L>,
+}
+
+impl<L> Default for ChunkQueues<L> {
+    fn default() -> Self {
+        Self {
+            load: ChunkQueue::new(),
+            mesh: ChunkQueue::new(),
+            unload: ChunkQueue::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Creates the data of a chunk of the layer `L` when it is loaded.
+pub struct ChunkGenerator<L: ChunkLayer>(
+    pub Box<dyn Fn(ChunkPosition, ChunkSize) -> ChunkData<L::Cell> + Send + Sync>,
+);
+
+impl<L: ChunkLayer> ChunkGenerator<L> {
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(ChunkPosition, ChunkSize) -> ChunkData<L::Cell> + Send + Sync + 'static,
+    {
+        Self(Box::new(f))
+    }
+}
+
+/// Builds the mesh of a chunk of the layer `L` from its data and the
+/// data of the neighbours it borders. The mesh is relative to the
+/// center of the chunk.
+///
+/// The `ChunkView` of a chunk only has the neighbours the mesher samples,
+/// the ones of `ChunkView::SQUARES` unless set with `with_neighbours`.
+/// A chunk is remeshed when one of them loads, or when cells it samples
+/// are edited.
+pub struct ChunkMesher<L: ChunkLayer>(
+    pub Box<dyn Fn(&ChunkView<L::Cell>, &ChunkInfo<L>) -> Mesh + Send + Sync>,
+    Option<ChunkRemesher<L>>,
+    Option<ChunkOutliner<L>>,
+    Vec<(i32, i32)>,
+);
+
+/// Patches the `IncrementalMesh` of a chunk where its cells changed,
+/// given the dirty rectangle of the chunk.
+type ChunkRemesher<L> = Box<
+    dyn Fn(
+            &mut IncrementalMesh,
+            &ChunkView<<L as ChunkLayer>::Cell>,
+            &ChunkInfo<L>,
+            Option<CellRect>,
+        ) + Send
+        + Sync,
+>;
+
+/// Builds the contours of a chunk, for its `Polylines` collider
+/// when it is meshed whole.
+type ChunkOutliner<L> =
+    Box<dyn Fn(&ChunkView<<L as ChunkLayer>::Cell>, &ChunkInfo<L>) -> Vec<Contour> + Send + Sync>;
+
+impl<L: ChunkLayer> ChunkMesher<L> {
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(&ChunkView<L::Cell>, &ChunkInfo<L>) -> Mesh + Send + Sync + 'static,
+    {
+        Self(Box::new(f), None, None, ChunkView::<()>::SQUARES.to_vec())
+    }
+
+    /// Sample the neighbours at `neighbours` from every chunk, like
+    /// `ChunkView::NEIGHBOURS` for autotiling or dual contouring.
+    pub fn with_neighbours(mut self, neighbours: &[(i32, i32)]) -> Self {
+        self.3 = neighbours.to_vec();
+        self
+    }
+
+    /// The offsets of the neighbours the mesher samples.
+    pub fn neighbours(&self) -> &[(i32, i32)] {
+        &self.3
+    }
+
+    /// Mesh chunks with `squares`, with `density` reading the density of
+    /// a cell. Every chunk keeps its `IncrementalMesh`, which is patched
+    /// where the cells of the chunk changed instead of being built again,
+    /// and written to the mesh asset of the chunk in place.
+    /// `Polylines` colliders are built from the contours of the chunks,
+    /// which the incremental mesh keeps per square and only joins again
+    /// when a patch changed them.
+    pub fn marching_squares<F>(squares: MarchingSquares, density: F) -> Self
+    where
+        F: Fn(&L::Cell) -> f32 + Clone + Send + Sync + 'static,
+    {
+        let (full, outline) = (density.clone(), density.clone());
+
+        Self(
+            Box::new(move |view, info| squares.mesh(*view, info.cell_size, &full)),
+            Some(Box::new(move |mesh, view, info, dirty| {
+                squares.remesh(mesh, *view, info.cell_size, &density, dirty)
+            })),
+            Some(Box::new(move |view, info| {
+                squares.contours(*view, info.cell_size, &outline)
+            })),
+            ChunkView::<()>::SQUARES.to_vec(),
+        )
+    }
+}
+
+/// What the collider of a chunk is built from after it is meshed.
+enum ColliderSource {
+    Mesh,
+    Contours(Vec<Contour>),
+    /// The contours of the chunk are the same, so is its collider.
+    Unchanged,
+}
+
+/// Marks a chunk that has been unloaded and will be
+/// despawned at the start of the next frame.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct DespawnChunk;
+
+/// Loads and unloads the chunks of the layer `L` around every
+/// `ChunkLoader` and every ticket in `ChunkTickets`.
+///
+/// A `ChunkGenerator<L>` and `ChunkMesher<L>` resource must be added
+/// to the app. Colliders are built from chunk meshes by the
+/// `ChunkColliders<L>` backend, which creates none by default.
+///
+/// Cells are edited by sending `CellEdit<L>` events.
+///
+/// The amount of work done per frame in each layer is limited by
+/// the `ChunkBudget` resource, with the closest chunks loaded first.
+///
+/// Chunks far from every loader are meshed with fewer cells
+/// when a `ChunkLods<L>` resource with distances is added.
+///
+/// The first layer added decides the `ChunkGrid` shared by every layer,
+/// unless one was inserted before. Every layer must cover the same area
+/// with its chunks.
+pub struct ChunkPlugin<L> {
+    pub info: ChunkInfo<L>,
+}
+
+impl<L> ChunkPlugin<L> {
+    pub fn new(chunk_size: ChunkSize, cell_size: CellSize) -> Self {
+        Self {
+            info: ChunkInfo::new(chunk_size, cell_size),
+        }
+    }
+}
+
+impl<L> Default for ChunkPlugin<L> {
+    fn default() -> Self {
+        Self {
+            info: ChunkInfo::default(),
+        }
+    }
+}
+
+impl<L: ChunkLayer> Plugin for ChunkPlugin<L> {
+    fn build(&self, app: &mut AppBuilder) {
+        if app.resources().get::<SharedChunkStages>().is_none() {
+            build_shared(app);
+        }
+
+        let grid = app.resources().get::<ChunkGrid>().map(|grid| *grid);
+
+        match grid {
+            Some(grid) => assert!(
+                grid.is_compatible(self.info.grid()),
+                "the chunks of every layer must cover the same area"
+            ),
+            None => {
+                app.add_resource(self.info.grid());
+            }
+        }
+
+        app.add_resource(self.info)
+            .add_event::<CellEdit<L>>()
+            .init_resource::<ChunkMap<L>>()
+            .init_resource::<ChunkQueues<L>>()
+            .init_resource::<ChunkColliders<L>>()
+            .init_resource::<ChunkLods<L>>()
+            .init_resource::<ChunkOwnedStore<L>>()
+            .add_system_to_stage(stage::CHUNK_EDIT, apply_cell_edits::<L>.system())
+            .add_system_to_stage(stage::CHUNK_LOAD, shift_chunk_colliders::<L>.system())
+            .add_system_to_stage(stage::CHUNK_LOAD, queue_chunks::<L>.system())
+            .add_system_to_stage(stage::CHUNK_LOAD, spawn_chunks::<L>.system())
+            .add_system_to_stage(stage::CHUNK_LOAD, select_chunk_lods::<L>.system())
+            .add_system_to_stage(stage::CHUNK_LOAD, unload_chunks::<L>.system())
+            .add_system_to_stage(stage::CHUNK_MESH, mesh_chunks::<L>.system())
+            .add_system_to_stage(stage::CHUNK_MESH, parent_owned_entities::<L>.system())
+            .add_system_to_stage(stage::CHUNK_SAVE, save_owned_entities::<L>.system())
+            .add_system_to_stage(stage::CHUNK_SAVE, restore_owned_entities::<L>.system())
+            .add_system_to_stage(
+                bevy::app::stage::PRE_UPDATE,
+                despawn_marked_chunks::<L>.system(),
+            );
+    }
+}
+
+/// Marks an app that has the resources, stages and systems shared by
+/// every layer, added along with the first `ChunkPlugin`.
+struct SharedChunkStages;
+
+/// Resources, stages and systems shared by every layer.
+fn build_shared(app: &mut AppBuilder) {
+    app.add_resource(SharedChunkStages)
+        .init_resource::<ChunkBudget>()
+        .init_resource::<ChunkTickets>()
+        .init_resource::<ChunkEntities>()
+        .init_resource::<ChunkOwnedComponents>()
+        .init_resource::<FloatingOrigin>()
+        .add_event::<OriginShifted>()
+        .add_stage_before(
+            bevy::app::stage::PRE_UPDATE,
+            stage::CHUNK_SAVE,
+            SystemStage::serial(),
+        )
+        .add_stage_after(
+            bevy::app::stage::UPDATE,
+            stage::CHUNK_EDIT,
+            SystemStage::parallel(),
+        )
+        .add_stage_after(
+            stage::CHUNK_EDIT,
+            stage::CHUNK_LOAD,
+            SystemStage::parallel(),
+        )
+        .add_stage_after(
+            stage::CHUNK_LOAD,
+            stage::CHUNK_MESH,
+            SystemStage::parallel(),
+        )
+        .add_system_to_stage(stage::CHUNK_LOAD, recentre_floating_origin.system())
+        .add_system_to_stage(stage::CHUNK_LOAD, index_chunk_entities.system())
+        .add_system_to_stage(stage::CHUNK_LOAD, expire_tickets.system())
+        .add_system_to_stage(
+            bevy::app::stage::PRE_UPDATE,
+            insert_chunk_positions.system(),
+        )
+        .add_system_to_stage(bevy::app::stage::PRE_UPDATE, track_chunk_positions.system());
+}
+
+fn expire_tickets(time: Res<Time>, mut tickets: ResMut<ChunkTickets>) {
+    tickets.remove_expired(time.seconds_since_startup());
+}
+
+/// Rebuild the load and unload queues from the current loaders and tickets.
+fn queue_chunks<L: ChunkLayer>(
+    info: Res<ChunkInfo<L>>,
+    origin: Res<FloatingOrigin>,
+    map: Res<ChunkMap<L>>,
+    tickets: Res<ChunkTickets>,
+    mut queues: ResMut<ChunkQueues<L>>,
+    loaders: Query<(
+        &ChunkLoader,
+        &ChunkPosition,
+        Option<&GlobalTransform>,
+        Option<&ChunkLoaderVelocity>,
+    )>,
+) {
+    let mut wanted: HashMap<ChunkPosition, f32> = HashMap::new();
+    let mut centers = Vec::new();
+
+    for (loader, &center, transform, velocity) in loaders.iter() {
+        let loader_world = match transform {
+            Some(transform) => transform.translation.truncate(),
+            None => origin.chunk_to_local(info.chunk_size, info.cell_size, center),
+        };
+
+        for position in center.iter_radius(loader.radius) {
+            let chunk_world = origin.chunk_to_local(info.chunk_size, info.cell_size, position);
+            let priority = loader.priority(loader_world, velocity.map(|v| v.0), chunk_world);
+
+            let entry = wanted.entry(position).or_insert(priority);
+            *entry = entry.min(priority);
+        }
+
+        centers.push(center);
+    }
+
+    for (_, ticket) in tickets.iter() {
+        let ticket_world = origin.chunk_to_local(info.chunk_size, info.cell_size, ticket.position);
+
+        for position in ticket.position.iter_radius(ticket.radius) {
+            let chunk_world = origin.chunk_to_local(info.chunk_size, info.cell_size, position);
+            let priority = (chunk_world - ticket_world).length();
+
+            let entry = wanted.entry(position).or_insert(priority);
+            *entry = entry.min(priority);
+        }
+
+        centers.push(ticket.position);
+    }
+
+    let queues = &mut *queues;
+
+    queues.load.clear();
+    for (&position, &priority) in wanted.iter() {
+        if !map.0.contains_key(&position) {
+            queues.load.push(position, priority);
+        }
+    }
+
+    // The chunks furthest from every loader are unloaded first:
+    queues.unload.clear();
+    for &position in map.0.keys() {
+        if !wanted.contains_key(&position) {
+            let distance = centers
+                .iter()
+                .map(|&center| position.distance(center))
+                .min()
+                .unwrap_or(0);
+
+            queues.unload.push(position, -(distance as f32));
+        }
+    }
+}
+
+fn spawn_chunks<L: ChunkLayer>(
+    commands: &mut Commands,
+    info: Res<ChunkInfo<L>>,
+    origin: Res<FloatingOrigin>,
+    budget: Res<ChunkBudget>,
+    generator: Res<ChunkGenerator<L>>,
+    mesher: Res<ChunkMesher<L>>,
+    mut map: ResMut<ChunkMap<L>>,
+    mut queues: ResMut<ChunkQueues<L>>,
+) {
+    for _ in 0..budget.spawns {
+        let (position, priority) = match queues.load.pop() {
+            Some(next) => next,
+            None => break,
+        };
+
+        let data = (generator.0)(position, info.chunk_size);
+        let translation = origin
+            .chunk_to_local(info.chunk_size, info.cell_size, position)
+            .extend(0.0);
+
+        let entity = commands
+            .spawn((
+                Chunk {
+                    mesh: Handle::default(),
+                    position,
+                    data,
+                },
+                position,
+                L::default(),
+                Transform::from_translation(translation),
+                GlobalTransform::default(),
+            ))
+            .current_entity()
+            .unwrap();
+
+        map.0.insert(position, entity);
+        queues.mesh.push(position, priority);
+
+        // The chunks bordering this one can now close the gap to it:
+        for &(x, y) in mesher.neighbours() {
+            let dependent = position - (x, y).into();
+
+            if map.0.contains_key(&dependent) && !queues.mesh.contains(dependent) {
+                queues.mesh.push(dependent, priority);
+            }
+        }
+    }
+}
+
+/// Move every chunk to the level of detail for its distance to the nearest
+/// loader, remeshing it and its neighbours when it changes.
+fn select_chunk_lods<L: ChunkLayer>(
+    info: Res<ChunkInfo<L>>,
+    origin: Res<FloatingOrigin>,
+    map: Res<ChunkMap<L>>,
+    mut lods: ResMut<ChunkLods<L>>,
+    mut queues: ResMut<ChunkQueues<L>>,
+    loaders: Query<(&ChunkPosition, Option<&GlobalTransform>), With<ChunkLoader>>,
+) {
+    if lods.distances().is_empty() {
+        return;
+    }
+
+    let loaders: Vec<Vec2> = loaders
+        .iter()
+        .map(|(&center, transform)| match transform {
+            Some(transform) => transform.translation.truncate(),
+            None => origin.chunk_to_local(info.chunk_size, info.cell_size, center),
+        })
+        .collect();
+
+    if loaders.is_empty() {
+        return;
+    }
+
+    lods.retain(|position| map.0.contains_key(&position));
+
+    for &position in map.0.keys() {
+        let chunk_world = origin.chunk_to_local(info.chunk_size, info.cell_size, position);
+        let distance = loaders
+            .iter()
+            .map(|&loader| (chunk_world - loader).length())
+            .fold(f32::INFINITY, f32::min);
+
+        if lods.update(position, info.chunk_size, distance) {
+            // The neighbours blend their borders with the cells of the chunk:
+            for neighbour in position.iter_radius(1) {
+                if map.0.contains_key(&neighbour) && !queues.mesh.contains(neighbour) {
+                    queues.mesh.push(neighbour, distance);
+                }
+            }
+        }
+    }
+}
+
+fn mesh_chunks<L: ChunkLayer>(
+    commands: &mut Commands,
+    info: Res<ChunkInfo<L>>,
+    origin: Res<FloatingOrigin>,
+    budget: Res<ChunkBudget>,
+    mesher: Res<ChunkMesher<L>>,
+    colliders: Res<ChunkColliders<L>>,
+    mut lods: ResMut<ChunkLods<L>>,
+    map: Res<ChunkMap<L>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut queues: ResMut<ChunkQueues<L>>,
+    mut chunks: QuerySet<(
+        Query<&Chunk<L::Cell>, With<L>>,
+        Query<
+            (
+                &mut Chunk<L::Cell>,
+                Option<&ChunkCollider>,
+                Option<&mut IncrementalMesh>,
+            ),
+            With<L>,
+        >,
+    )>,
+) {
+    let mut uploaded = 0;
+
+    while uploaded < budget.mesh_uploads {
+        let (position, _) = match queues.mesh.pop() {
+            Some(next) => next,
+            None => break,
+        };
+
+        let entity = match map.0.get(&position) {
+            Some(&entity) => entity,
+            None => continue,
+        };
+
+        // Take the incremental mesh out of the chunk while its neighbours are read:
+        let (dirty, incremental) = match chunks.q1_mut().get_mut(entity) {
+            Ok((mut chunk, _, incremental)) => (
+                chunk.data.take_dirty(),
+                incremental.map(|mut mesh| std::mem::take(&mut *mesh)),
+            ),
+            Err(_) => continue,
+        };
+        let mut incremental = incremental.unwrap_or_default();
+
+        let (mesh, source) = {
+            let data = chunks.q0();
+            let chunk = match data.get(entity) {
+                Ok(chunk) => chunk,
+                Err(_) => continue,
+            };
+
+            let view = ChunkView::with_neighbours(&chunk.data, |offset| {
+                if !mesher.neighbours().contains(&(offset.x, offset.y)) {
+                    return None;
+                }
+
+                let neighbour = *map.0.get(&(position + offset))?;
+                data.get(neighbour).ok().map(|chunk| &chunk.data)
+            })
+            .at(position);
+
+            // Polylines follow the contours when the mesher has them,
+            // the outlines of the mesh have edges along the chunk borders:
+            let polylines = colliders.decomposition == ColliderDecomposition::Polylines;
+            let outline = |view: &ChunkView<L::Cell>, info: &ChunkInfo<L>| match &mesher.2 {
+                Some(outline) if polylines => ColliderSource::Contours(outline(view, info)),
+                _ => ColliderSource::Mesh,
+            };
+
+            let level = lods.level(position);
+
+            match (lods.cells(&view), &mesher.1) {
+                (Some(cells), _) => {
+                    // The incremental mesh only has the cells of the chunk:
+                    incremental.clear();
+
+                    let info = info.at_level(level);
+                    let view = cells.view(position);
+                    (Some((mesher.0)(&view, &info)), outline(&view, &info))
+                }
+                (None, Some(remesh)) => {
+                    remesh(&mut incremental, &view, &info, dirty);
+
+                    // The contours are only joined again when a square changed them:
+                    let source = match incremental.take_changed_contours() {
+                        _ if !polylines || mesher.2.is_none() => ColliderSource::Mesh,
+                        Some(contours) => ColliderSource::Contours(contours),
+                        None => ColliderSource::Unchanged,
+                    };
+                    (None, source)
+                }
+                (None, None) => (Some((mesher.0)(&view, &info)), outline(&view, &info)),
+            }
+        };
+
+        if let Ok((mut chunk, collider, kept_mesh)) = chunks.q1_mut().get_mut(entity) {
+            // Chunks keep their mesh asset, patched meshes are written to it in place:
+            match meshes.get_mut(&chunk.mesh) {
+                Some(existing) => match mesh {
+                    Some(mesh) => *existing = mesh,
+                    None => incremental.write_mesh(existing),
+                },
+                None => {
+                    let mesh = mesh.unwrap_or_else(|| {
+                        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+                        incremental.write_mesh(&mut mesh);
+                        mesh
+                    });
+                    let handle = meshes.add(mesh);
+
+                    commands.insert_one(entity, handle.clone());
+                    chunk.mesh = handle;
+                }
+            }
+
+            match kept_mesh {
+                Some(mut kept_mesh) => *kept_mesh = incremental,
+                None if mesher.1.is_some() => {
+                    commands.insert_one(entity, incremental);
+                }
+                None => {}
+            }
+
+            let translation = origin.chunk_to_local(info.chunk_size, info.cell_size, position);
+
+            let shape = match source {
+                ColliderSource::Mesh => meshes.get(&chunk.mesh).and_then(|mesh| {
+                    ChunkColliderShape::from_mesh_with(mesh, colliders.decomposition)
+                }),
+                ColliderSource::Contours(contours) => ChunkColliderShape::from_contours(&contours),
+                ColliderSource::Unchanged => {
+                    uploaded += 1;
+                    continue;
+                }
+            };
+
+            match (shape, collider) {
+                (Some(shape), Some(_)) => {
+                    colliders
+                        .backend
+                        .update(commands, entity, translation, &shape)
+                }
+                (Some(shape), None) => {
+                    colliders
+                        .backend
+                        .create(commands, entity, translation, &shape);
+                    commands.insert_one(entity, ChunkCollider);
+                }
+                (None, Some(_)) => {
+                    colliders.backend.remove(commands, entity);
+                    commands.remove_one::<ChunkCollider>(entity);
+                }
+                (None, None) => {}
+            }
+
+            uploaded += 1;
+        }
+    }
+}
+
+fn unload_chunks<L: ChunkLayer>(
+    commands: &mut Commands,
+    budget: Res<ChunkBudget>,
+    mut map: ResMut<ChunkMap<L>>,
+    mut queues: ResMut<ChunkQueues<L>>,
+) {
+    for _ in 0..budget.despawns {
+        let (position, _) = match queues.unload.pop() {
+            Some(next) => next,
+            None => break,
+        };
+
+        if let Some(entity) = map.0.remove(&position) {
+            commands.insert_one(entity, DespawnChunk);
+        }
+
+        queues.mesh.remove(position);
+    }
+}
+
+fn despawn_marked_chunks<L: ChunkLayer>(
+    commands: &mut Commands,
+    colliders: Res<ChunkColliders<L>>,
+    chunks: Query<(Entity, Option<&ChunkCollider>), (With<DespawnChunk>, With<L>)>,
+) {
+    for (entity, collider) in chunks.iter() {
+        if collider.is_some() {
+            colliders.backend.remove(commands, entity);
+        }
+
+        commands.despawn_recursive(entity);
+    }
+}
@@ -42,6 +42,31 @@ impl ChunkPosition {
     pub fn as_vec2(&self) -> Vec2 {
         Vec2::new(self.x as f32, self.y as f32)
     }
+
+    /// The number of chunks between `self` and `other`, counting
+    /// diagonal steps as one.
+    pub fn distance(&self, other: ChunkPosition) -> u32 {
+        let delta = *self - other;
+
+        delta.x.abs().max(delta.y.abs()) as u32
+    }
+
+    /// Iterate over the square of chunks at most `radius`
+    /// chunks away from `self`, including `self`.
+    pub fn iter_radius(self, radius: u32) -> impl Iterator<Item = ChunkPosition> {
+        let r = radius as i32;
+
+        Self::iter_rect(self - (r, r).into(), self + (r, r).into())
+    }
+
+    /// Iterate over every chunk between `min` and `max` inclusive,
+    /// one row at a time.
+    pub fn iter_rect(
+        min: ChunkPosition,
+        max: ChunkPosition,
+    ) -> impl Iterator<Item = ChunkPosition> {
+        (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| ChunkPosition::new(x, y)))
+    }
 }
 
 impl Add for ChunkPosition {
@@ -174,6 +199,128 @@ impl From<&[i32; 2]> for CellPosition {
     }
 }
 
+/// A rectangle of cells in a chunk, `min` and `max` inclusive.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct CellRect {
+    pub min: CellPosition,
+    pub max: CellPosition,
+}
+
+impl CellRect {
+    /// The rectangle between two opposite corners.
+    pub fn new(a: CellPosition, b: CellPosition) -> Self {
+        Self {
+            min: CellPosition::new(a.x.min(b.x), a.y.min(b.y)),
+            max: CellPosition::new(a.x.max(b.x), a.y.max(b.y)),
+        }
+    }
+
+    /// A rectangle containing a single cell.
+    pub fn cell(pos: CellPosition) -> Self {
+        Self { min: pos, max: pos }
+    }
+
+    pub fn contains(&self, pos: CellPosition) -> bool {
+        (self.min.x..=self.max.x).contains(&pos.x) && (self.min.y..=self.max.y).contains(&pos.y)
+    }
+
+    /// The smallest rectangle containing both `self` and `other`.
+    pub fn union(&self, other: CellRect) -> Self {
+        Self::new(
+            CellPosition::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y)),
+            CellPosition::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y)),
+        )
+    }
+
+    /// The cells in both `self` and `other`, if there are any.
+    pub fn intersection(&self, other: CellRect) -> Option<Self> {
+        let min = CellPosition::new(self.min.x.max(other.min.x), self.min.y.max(other.min.y));
+        let max = CellPosition::new(self.max.x.min(other.max.x), self.max.y.min(other.max.y));
+
+        if min.x > max.x || min.y > max.y {
+            return None;
+        }
+
+        Some(Self { min, max })
+    }
+
+    pub fn width(&self) -> usize {
+        (self.max.x - self.min.x + 1) as usize
+    }
+
+    pub fn height(&self) -> usize {
+        (self.max.y - self.min.y + 1) as usize
+    }
+
+    /// Iterate over every cell starting at the top left
+    /// going to the bottom right.
+    pub fn iter(self) -> impl Iterator<Item = CellPosition> {
+        (self.min.y..=self.max.y)
+            .rev()
+            .flat_map(move |y| (self.min.x..=self.max.x).map(move |x| CellPosition::new(x, y)))
+    }
+}
+
+/// The position of a cell in the world, counted in cells from
+/// the center of the chunk at 0, 0.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct GlobalCellPosition {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl GlobalCellPosition {
+    pub fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    /// The cell closest to a point in world coordinates.
+    pub fn from_world(cell_size: CellSize, world: Vec2) -> Self {
+        let cell = (world / cell_size.as_vec2()).round();
+
+        Self::new(cell.x as i32, cell.y as i32)
+    }
+
+    /// The center of the cell in world coordinates.
+    pub fn to_world(&self, cell_size: CellSize) -> Vec2 {
+        Vec2::new(self.x as f32, self.y as f32) * cell_size.as_vec2()
+    }
+
+    /// The position of the cell at `cell` in the chunk at `chunk`.
+    pub fn from_chunk(chunk_size: ChunkSize, chunk: ChunkPosition, cell: CellPosition) -> Self {
+        Self::new(
+            chunk.x * chunk_size.width as i32 + cell.x,
+            chunk.y * chunk_size.height as i32 + cell.y,
+        )
+    }
+
+    /// The chunk this cell is in and its position in that chunk.
+    pub fn to_chunk(&self, chunk_size: ChunkSize) -> (ChunkPosition, CellPosition) {
+        let (width, height) = (chunk_size.width as i32, chunk_size.height as i32);
+        let (top_left, bottom_right) = (chunk_size.top_left(), chunk_size.bottom_right());
+
+        let chunk = ChunkPosition::new(
+            (self.x - top_left.x).div_euclid(width),
+            (self.y - bottom_right.y).div_euclid(height),
+        );
+        let cell = CellPosition::new(self.x - chunk.x * width, self.y - chunk.y * height);
+
+        (chunk, cell)
+    }
+}
+
+impl From<(i32, i32)> for GlobalCellPosition {
+    fn from(t: (i32, i32)) -> Self {
+        Self { x: t.0, y: t.1 }
+    }
+}
+
+impl From<[i32; 2]> for GlobalCellPosition {
+    fn from([x, y]: [i32; 2]) -> Self {
+        Self { x, y }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -243,6 +390,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn chunk_ranges() {
+        let center = ChunkPosition::new(2, -3);
+
+        let square: Vec<ChunkPosition> = center.iter_radius(1).collect();
+        assert_eq!(9, square.len());
+        assert_eq!(ChunkPosition::new(1, -4), square[0]);
+        assert_eq!(ChunkPosition::new(3, -2), square[8]);
+        assert!(square.iter().all(|&pos| center.distance(pos) <= 1));
+
+        assert_eq!(1, center.iter_radius(0).count());
+        assert_eq!(
+            6,
+            ChunkPosition::iter_rect((0, 0).into(), (2, 1).into()).count()
+        );
+    }
+
     #[test]
     fn cell_positions_easy() {
         let chunk_size = ChunkSize::new(64, 64);
@@ -332,4 +496,33 @@ mod tests {
             CellPosition::from_world(chunk_size, cell_size, world)
         );
     }
+
+    #[test]
+    fn global_cell_positions() {
+        let chunk_size = ChunkSize::new(10, 10);
+
+        let cells: &[(GlobalCellPosition, ChunkPosition, CellPosition)] = &[
+            ((0, 0).into(), (0, 0).into(), (0, 0).into()),
+            ((4, 5).into(), (0, 0).into(), (4, 5).into()),
+            ((5, 6).into(), (1, 1).into(), (-5, -4).into()),
+            ((-5, -4).into(), (0, 0).into(), (-5, -4).into()),
+            ((-6, -5).into(), (-1, -1).into(), (4, 5).into()),
+            ((-23, 17).into(), (-2, 2).into(), (-3, -3).into()),
+        ];
+
+        for &(global, chunk, cell) in cells {
+            assert!(chunk_size.contains(cell));
+            assert_eq!((chunk, cell), global.to_chunk(chunk_size));
+            assert_eq!(
+                global,
+                GlobalCellPosition::from_chunk(chunk_size, chunk, cell)
+            );
+        }
+
+        let cell_size = CellSize::new(8, 8);
+        assert_eq!(
+            GlobalCellPosition::new(-2, 1),
+            GlobalCellPosition::from_world(cell_size, Vec2::new(-14.0, 5.0))
+        );
+    }
 }
@@ -0,0 +1,168 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::ChunkPosition;
+
+/// How much chunk work the plugin is allowed to do in a single frame.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ChunkBudget {
+    /// Chunk entities spawned per frame.
+    pub spawns: usize,
+    /// Chunk meshes built and uploaded per frame.
+    pub mesh_uploads: usize,
+    /// Chunk entities despawned per frame.
+    pub despawns: usize,
+}
+
+impl ChunkBudget {
+    pub fn new(spawns: usize, mesh_uploads: usize, despawns: usize) -> Self {
+        Self {
+            spawns,
+            mesh_uploads,
+            despawns,
+        }
+    }
+
+    /// A budget that never limits any work.
+    pub fn unlimited() -> Self {
+        Self::new(usize::MAX, usize::MAX, usize::MAX)
+    }
+}
+
+impl Default for ChunkBudget {
+    fn default() -> Self {
+        Self::new(4, 4, 8)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct QueuedChunk {
+    priority: f32,
+    position: ChunkPosition,
+}
+
+impl PartialEq for QueuedChunk {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedChunk {}
+
+impl PartialOrd for QueuedChunk {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedChunk {
+    // `BinaryHeap` is a max heap, so the lowest priority value
+    // has to compare as the greatest.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .priority
+            .partial_cmp(&self.priority)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| other.position.cmp(&self.position))
+    }
+}
+
+/// A queue of chunks waiting for work, ordered by priority.
+/// Chunks with a *lower* priority value are popped first.
+///
+/// Each position is queued at most once, pushing a position
+/// that is already queued replaces its priority.
+#[derive(Debug, Default, Clone)]
+pub struct ChunkQueue {
+    heap: BinaryHeap<QueuedChunk>,
+    queued: HashMap<ChunkPosition, f32>,
+}
+
+impl ChunkQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, position: ChunkPosition, priority: f32) {
+        self.queued.insert(position, priority);
+        self.heap.push(QueuedChunk { priority, position });
+    }
+
+    /// Pop the most urgent chunk along with its priority.
+    pub fn pop(&mut self) -> Option<(ChunkPosition, f32)> {
+        while let Some(QueuedChunk { priority, position }) = self.heap.pop() {
+            // Removed and re-prioritised chunks leave stale entries
+            // behind in the heap, skip them:
+            match self.queued.get(&position) {
+                Some(&current) if current.to_bits() == priority.to_bits() => {
+                    self.queued.remove(&position);
+                    return Some((position, priority));
+                }
+                _ => continue,
+            }
+        }
+
+        None
+    }
+
+    pub fn remove(&mut self, position: ChunkPosition) -> bool {
+        self.queued.remove(&position).is_some()
+    }
+
+    pub fn contains(&self, position: ChunkPosition) -> bool {
+        self.queued.contains_key(&position)
+    }
+
+    pub fn priority(&self, position: ChunkPosition) -> Option<f32> {
+        self.queued.get(&position).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queued.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queued.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.queued.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pops_lowest_priority_first() {
+        let mut queue = ChunkQueue::new();
+        queue.push((0, 0).into(), 3.0);
+        queue.push((1, 0).into(), 1.0);
+        queue.push((2, 0).into(), 2.0);
+
+        assert_eq!(Some(((1, 0).into(), 1.0)), queue.pop());
+        assert_eq!(Some(((2, 0).into(), 2.0)), queue.pop());
+        assert_eq!(Some(((0, 0).into(), 3.0)), queue.pop());
+        assert_eq!(None, queue.pop());
+    }
+
+    #[test]
+    fn reprioritise_and_remove() {
+        let mut queue = ChunkQueue::new();
+        queue.push((0, 0).into(), 1.0);
+        queue.push((1, 0).into(), 2.0);
+        queue.push((2, 0).into(), 3.0);
+
+        queue.push((2, 0).into(), 0.5);
+        assert!(queue.remove((0, 0).into()));
+        assert!(!queue.remove((0, 0).into()));
+        assert_eq!(2, queue.len());
+
+        assert_eq!(Some(((2, 0).into(), 0.5)), queue.pop());
+        assert_eq!(Some(((1, 0).into(), 2.0)), queue.pop());
+        assert_eq!(None, queue.pop());
+        assert!(queue.is_empty());
+    }
+}
@@ -1,5 +1,7 @@
 use bevy::prelude::Vec2;
 
+use crate::CellPosition;
+
 /// The size of a chunk in cells.
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct ChunkSize {
@@ -23,6 +25,29 @@ impl ChunkSize {
     pub fn as_vec2(&self) -> Vec2 {
         Vec2::new(self.width as f32, self.height as f32)
     }
+
+    /// The position of the top left cell in a chunk of this size.
+    pub fn top_left(&self) -> CellPosition {
+        CellPosition::new(-(self.width as i32 / 2), self.height as i32 / 2)
+    }
+
+    /// The position of the bottom right cell in a chunk of this size.
+    pub fn bottom_right(&self) -> CellPosition {
+        let top_left = self.top_left();
+
+        CellPosition::new(
+            top_left.x + self.width as i32 - 1,
+            top_left.y - self.height as i32 + 1,
+        )
+    }
+
+    /// Whether a chunk of this size has a cell at `pos`.
+    pub fn contains(&self, pos: CellPosition) -> bool {
+        let (top_left, bottom_right) = (self.top_left(), self.bottom_right());
+
+        (top_left.x..=bottom_right.x).contains(&pos.x)
+            && (bottom_right.y..=top_left.y).contains(&pos.y)
+    }
 }
 
 /// The size of each cell in the chunk.
@@ -0,0 +1,169 @@
+use bevy::prelude::*;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{ChunkPosition, ChunkTracked};
+
+/// Every `ChunkTracked` entity, grouped by the chunk it is in.
+#[derive(Debug, Default)]
+pub struct ChunkEntities {
+    chunks: HashMap<ChunkPosition, HashSet<Entity>>,
+    entities: HashMap<Entity, ChunkPosition>,
+}
+
+impl ChunkEntities {
+    /// Add `entity` to the chunk at `position`, moving it
+    /// out of the chunk it was in before.
+    pub fn insert(&mut self, entity: Entity, position: ChunkPosition) {
+        if let Some(previous) = self.entities.insert(entity, position) {
+            if previous == position {
+                return;
+            }
+
+            self.remove_from_chunk(entity, previous);
+        }
+
+        self.chunks.entry(position).or_default().insert(entity);
+    }
+
+    /// Remove `entity`, returning the chunk it was in.
+    pub fn remove(&mut self, entity: Entity) -> Option<ChunkPosition> {
+        let position = self.entities.remove(&entity)?;
+        self.remove_from_chunk(entity, position);
+
+        Some(position)
+    }
+
+    fn remove_from_chunk(&mut self, entity: Entity, position: ChunkPosition) {
+        if let Some(entities) = self.chunks.get_mut(&position) {
+            entities.remove(&entity);
+
+            if entities.is_empty() {
+                self.chunks.remove(&position);
+            }
+        }
+    }
+
+    /// The chunk `entity` is in.
+    pub fn chunk_of(&self, entity: Entity) -> Option<ChunkPosition> {
+        self.entities.get(&entity).copied()
+    }
+
+    /// Every entity in the chunk at `position`.
+    pub fn in_chunk(&self, position: ChunkPosition) -> impl Iterator<Item = Entity> + '_ {
+        self.chunks
+            .get(&position)
+            .into_iter()
+            .flat_map(|entities| entities.iter().copied())
+    }
+
+    /// Every entity in the chunks between `min` and `max` inclusive.
+    pub fn in_rect(
+        &self,
+        min: ChunkPosition,
+        max: ChunkPosition,
+    ) -> impl Iterator<Item = Entity> + '_ {
+        ChunkPosition::iter_rect(min, max).flat_map(move |position| self.in_chunk(position))
+    }
+
+    /// Every entity in the chunks at most `radius` chunks away from `center`.
+    pub fn in_radius(
+        &self,
+        center: ChunkPosition,
+        radius: u32,
+    ) -> impl Iterator<Item = Entity> + '_ {
+        center
+            .iter_radius(radius)
+            .flat_map(move |position| self.in_chunk(position))
+    }
+
+    /// Every chunk with at least one entity in it.
+    pub fn chunks(&self) -> impl Iterator<Item = ChunkPosition> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+/// Keep `ChunkEntities` up to date as tracked entities
+/// move between chunks, stop being tracked or despawn.
+pub fn index_chunk_entities(
+    mut index: ResMut<ChunkEntities>,
+    moved: Query<(Entity, &ChunkPosition), (With<ChunkTracked>, Changed<ChunkPosition>)>,
+    tracked: Query<&ChunkTracked>,
+    positions: Query<&ChunkPosition>,
+) {
+    for &entity in tracked.removed::<ChunkTracked>() {
+        index.remove(entity);
+    }
+
+    for &entity in positions.removed::<ChunkPosition>() {
+        index.remove(entity);
+    }
+
+    for (entity, &position) in moved.iter() {
+        index.insert(entity, position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(entities: impl Iterator<Item = Entity>) -> Vec<Entity> {
+        let mut entities: Vec<Entity> = entities.collect();
+        entities.sort();
+        entities
+    }
+
+    #[test]
+    fn move_between_chunks() {
+        let mut index = ChunkEntities::default();
+        let (a, b) = (Entity::new(0), Entity::new(1));
+
+        index.insert(a, (0, 0).into());
+        index.insert(b, (0, 0).into());
+        assert_eq!(vec![a, b], sorted(index.in_chunk((0, 0).into())));
+
+        index.insert(a, (1, 0).into());
+        assert_eq!(vec![b], sorted(index.in_chunk((0, 0).into())));
+        assert_eq!(vec![a], sorted(index.in_chunk((1, 0).into())));
+        assert_eq!(Some((1, 0).into()), index.chunk_of(a));
+
+        assert_eq!(Some((0, 0).into()), index.remove(b));
+        assert_eq!(None, index.remove(b));
+        assert_eq!(0, index.in_chunk((0, 0).into()).count());
+        assert_eq!(1, index.chunks().count());
+        assert_eq!(1, index.len());
+    }
+
+    #[test]
+    fn range_queries() {
+        let mut index = ChunkEntities::default();
+        let entities: Vec<Entity> = (0..4).map(Entity::new).collect();
+
+        index.insert(entities[0], (0, 0).into());
+        index.insert(entities[1], (1, 1).into());
+        index.insert(entities[2], (-2, 0).into());
+        index.insert(entities[3], (3, -3).into());
+
+        assert_eq!(
+            vec![entities[0], entities[1]],
+            sorted(index.in_radius((0, 0).into(), 1))
+        );
+        assert_eq!(
+            vec![entities[0], entities[1], entities[2]],
+            sorted(index.in_radius((0, 0).into(), 2))
+        );
+        assert_eq!(
+            vec![entities[0], entities[3]],
+            sorted(index.in_rect((0, -3).into(), (3, 0).into()))
+        );
+    }
+}
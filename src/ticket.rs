@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use crate::ChunkPosition;
+
+/// Identifies a ticket added to `ChunkTickets`.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct TicketId(u64);
+
+/// Keeps the chunks around `position` loaded, the same way a
+/// `ChunkLoader` would, without needing an entity.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChunkTicket {
+    pub position: ChunkPosition,
+    /// Every chunk within `radius` chunks of `position` is kept loaded.
+    pub radius: u32,
+    /// When the ticket is removed, in seconds since startup.
+    pub expires_at: Option<f64>,
+}
+
+impl ChunkTicket {
+    pub fn new(position: impl Into<ChunkPosition>, radius: u32) -> Self {
+        Self {
+            position: position.into(),
+            radius,
+            expires_at: None,
+        }
+    }
+
+    pub fn with_expiry(mut self, expires_at: f64) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    pub fn is_expired(&self, now: f64) -> bool {
+        matches!(self.expires_at, Some(expires_at) if expires_at <= now)
+    }
+
+    /// Whether the ticket keeps `position` loaded.
+    pub fn covers(&self, position: ChunkPosition) -> bool {
+        self.position.distance(position) <= self.radius
+    }
+}
+
+/// Every ticket keeping chunks loaded. Any system can add or
+/// remove tickets, the plugin treats the chunks they cover like
+/// chunks around a `ChunkLoader`.
+#[derive(Debug, Default)]
+pub struct ChunkTickets {
+    next_id: u64,
+    tickets: HashMap<TicketId, ChunkTicket>,
+}
+
+impl ChunkTickets {
+    pub fn add(&mut self, ticket: ChunkTicket) -> TicketId {
+        let id = TicketId(self.next_id);
+        self.next_id += 1;
+
+        self.tickets.insert(id, ticket);
+        id
+    }
+
+    pub fn remove(&mut self, id: TicketId) -> Option<ChunkTicket> {
+        self.tickets.remove(&id)
+    }
+
+    pub fn get(&self, id: TicketId) -> Option<&ChunkTicket> {
+        self.tickets.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: TicketId) -> Option<&mut ChunkTicket> {
+        self.tickets.get_mut(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (TicketId, &ChunkTicket)> {
+        self.tickets.iter().map(|(&id, ticket)| (id, ticket))
+    }
+
+    /// Whether any ticket keeps `position` loaded.
+    pub fn is_ticketed(&self, position: ChunkPosition) -> bool {
+        self.tickets.values().any(|ticket| ticket.covers(position))
+    }
+
+    /// Remove every ticket that expired at or before `now`,
+    /// returning how many were removed.
+    pub fn remove_expired(&mut self, now: f64) -> usize {
+        let before = self.tickets.len();
+        self.tickets.retain(|_, ticket| !ticket.is_expired(now));
+
+        before - self.tickets.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tickets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tickets.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_and_remove() {
+        let mut tickets = ChunkTickets::default();
+
+        let machine = tickets.add(ChunkTicket::new((4, 4), 0));
+        let cutscene = tickets.add(ChunkTicket::new((-2, 0), 1));
+        assert_ne!(machine, cutscene);
+
+        assert!(tickets.is_ticketed((4, 4).into()));
+        assert!(!tickets.is_ticketed((4, 5).into()));
+        assert!(tickets.is_ticketed((-1, 1).into()));
+
+        assert_eq!(Some(ChunkTicket::new((4, 4), 0)), tickets.remove(machine));
+        assert_eq!(None, tickets.remove(machine));
+        assert!(!tickets.is_ticketed((4, 4).into()));
+        assert_eq!(1, tickets.len());
+    }
+
+    #[test]
+    fn expiry() {
+        let mut tickets = ChunkTickets::default();
+
+        let quest = tickets.add(ChunkTicket::new((0, 0), 2).with_expiry(10.0));
+        let forever = tickets.add(ChunkTicket::new((8, 8), 0));
+
+        assert_eq!(0, tickets.remove_expired(9.5));
+        assert_eq!(1, tickets.remove_expired(10.0));
+
+        assert!(tickets.get(quest).is_none());
+        assert!(tickets.get(forever).is_some());
+    }
+}
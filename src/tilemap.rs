@@ -0,0 +1,337 @@
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::pipeline::PrimitiveTopology;
+use bevy::sprite::{Rect, TextureAtlas};
+
+use crate::{CellPosition, CellSize, ChunkView};
+
+/// A tile of a `TextureAtlas`, along with how it is flipped and turned.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct AtlasIndex {
+    pub index: usize,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Quarter turns counter-clockwise, after flipping.
+    pub turns: u8,
+}
+
+impl AtlasIndex {
+    pub fn new(index: usize) -> Self {
+        Self {
+            index,
+            ..Default::default()
+        }
+    }
+
+    pub fn flip_x(mut self) -> Self {
+        self.flip_x = !self.flip_x;
+        self
+    }
+
+    pub fn flip_y(mut self) -> Self {
+        self.flip_y = !self.flip_y;
+        self
+    }
+
+    /// Turn the tile counter-clockwise by `turns` quarter turns.
+    pub fn rotate(mut self, turns: u8) -> Self {
+        self.turns = (self.turns + turns) % 4;
+        self
+    }
+}
+
+impl From<usize> for AtlasIndex {
+    fn from(index: usize) -> Self {
+        Self::new(index)
+    }
+}
+
+/// The neighbours of a tile that connect to it, one bit per neighbour
+/// clockwise from the top.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct NeighbourMask(pub u8);
+
+impl NeighbourMask {
+    pub const NORTH: u8 = 1;
+    pub const NORTH_EAST: u8 = 1 << 1;
+    pub const EAST: u8 = 1 << 2;
+    pub const SOUTH_EAST: u8 = 1 << 3;
+    pub const SOUTH: u8 = 1 << 4;
+    pub const SOUTH_WEST: u8 = 1 << 5;
+    pub const WEST: u8 = 1 << 6;
+    pub const NORTH_WEST: u8 = 1 << 7;
+
+    /// The offset of the neighbour of every bit, from the lowest bit.
+    pub const OFFSETS: [(i32, i32); 8] = [
+        (0, 1),
+        (1, 1),
+        (1, 0),
+        (1, -1),
+        (0, -1),
+        (-1, -1),
+        (-1, 0),
+        (-1, 1),
+    ];
+
+    pub fn contains(self, bits: u8) -> bool {
+        self.0 & bits == bits
+    }
+
+    /// The sides only, as 4 bits from the top clockwise,
+    /// for tilesets with 16 tiles.
+    pub fn sides(self) -> u8 {
+        (0..4).fold(0, |sides, side| {
+            sides | ((self.0 >> (side * 2)) & 1) << side
+        })
+    }
+
+    /// The mask without the corners whose two sides are not both set, leaving
+    /// the 47 different masks of "blob" tilesets.
+    pub fn blob(self) -> Self {
+        let mut mask = self.0;
+
+        for corner in &[1, 3, 5, 7] {
+            let (before, after) = (1 << (corner - 1), 1 << ((corner + 1) % 8));
+
+            if self.0 & before == 0 || self.0 & after == 0 {
+                mask &= !(1 << corner);
+            }
+        }
+
+        NeighbourMask(mask)
+    }
+}
+
+/// Builds meshes of chunks made of tiles, with a quad for every cell
+/// that has a tile and texture coordinates into a `TextureAtlas`.
+///
+/// Quads are centered on their cells, relative to the center of the
+/// chunk. Tiles whose index is not in the atlas are left out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TilemapMesher {
+    /// The size of the atlas texture, in pixels.
+    pub size: Vec2,
+    /// The area of every tile in the atlas texture, in pixels.
+    pub tiles: Vec<Rect>,
+}
+
+impl TilemapMesher {
+    pub fn new(atlas: &TextureAtlas) -> Self {
+        Self {
+            size: atlas.size,
+            tiles: atlas.textures.clone(),
+        }
+    }
+
+    /// An atlas of `columns` by `rows` tiles of `tile_size`,
+    /// numbered row by row from the top left.
+    pub fn grid(tile_size: Vec2, columns: usize, rows: usize) -> Self {
+        let tiles = (0..rows)
+            .flat_map(|y| (0..columns).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let min = Vec2::new(x as f32, y as f32) * tile_size;
+                Rect {
+                    min,
+                    max: min + tile_size,
+                }
+            })
+            .collect();
+
+        Self {
+            size: tile_size * Vec2::new(columns as f32, rows as f32),
+            tiles,
+        }
+    }
+
+    /// A mesh of the tiles of `view`, with `tile` picking the tile of
+    /// every cell or `None` for empty cells. It samples no neighbours,
+    /// so its `ChunkMesher` can be given none with `with_neighbours`.
+    pub fn mesh<'a, T: 'a, F>(
+        &self,
+        view: impl Into<ChunkView<'a, T>>,
+        cell_size: CellSize,
+        tile: F,
+    ) -> Mesh
+    where
+        F: Fn(&T) -> Option<AtlasIndex>,
+    {
+        let view = view.into();
+
+        self.build(view, cell_size, |pos| view.get(pos).and_then(&tile))
+    }
+
+    /// A mesh of the tiles of `view`, with `tile` picking the tile of every
+    /// cell from the mask of its neighbours that `connects` to it. Neighbours
+    /// in chunks that are not loaded do not connect. It samples all of
+    /// `ChunkView::NEIGHBOURS`, which its `ChunkMesher` must be given.
+    pub fn autotile_mesh<'a, T: 'a, C, F>(
+        &self,
+        view: impl Into<ChunkView<'a, T>>,
+        cell_size: CellSize,
+        connects: C,
+        tile: F,
+    ) -> Mesh
+    where
+        C: Fn(&T, &T) -> bool,
+        F: Fn(&T, NeighbourMask) -> Option<AtlasIndex>,
+    {
+        let view = view.into();
+
+        self.build(view, cell_size, |pos| {
+            let cell = view.get(pos)?;
+            let mask = NeighbourMask::OFFSETS
+                .iter()
+                .enumerate()
+                .filter(|&(_, &(dx, dy))| {
+                    matches!(
+                        view.get(CellPosition::new(pos.x + dx, pos.y + dy)),
+                        Some(neighbour) if connects(cell, neighbour)
+                    )
+                })
+                .fold(0, |mask, (bit, _)| mask | 1 << bit);
+
+            tile(cell, NeighbourMask(mask))
+        })
+    }
+
+    fn build<'a, T, F>(&self, view: ChunkView<'a, T>, cell_size: CellSize, tile: F) -> Mesh
+    where
+        F: Fn(CellPosition) -> Option<AtlasIndex>,
+    {
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut indices = Vec::new();
+
+        let size = cell_size.as_vec2();
+        let half = size / 2.0;
+
+        for pos in view.data.bounds().iter() {
+            let (tile, rect) =
+                match tile(pos).and_then(|tile| Some((tile, self.tiles.get(tile.index)?))) {
+                    Some(tile) => tile,
+                    None => continue,
+                };
+
+            let center = Vec2::new(pos.x as f32, pos.y as f32) * size;
+            let start = positions.len() as u32;
+
+            // Counter-clockwise from the bottom left:
+            for &(x, y) in &[(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                let corner = center + half * Vec2::new(x, y);
+                positions.push([corner.x, corner.y, 0.0]);
+            }
+
+            uvs.extend_from_slice(&self.uvs(tile, *rect));
+            indices.extend_from_slice(&[start, start + 1, start + 2, start, start + 2, start + 3]);
+        }
+
+        let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.set_indices(Some(Indices::U32(indices)));
+
+        mesh
+    }
+
+    /// The texture coordinates of the corners of a quad,
+    /// counter-clockwise from the bottom left.
+    fn uvs(&self, tile: AtlasIndex, rect: Rect) -> [[f32; 2]; 4] {
+        let (min, max) = (rect.min / self.size, rect.max / self.size);
+        let (mut left, mut right) = (min.x, max.x);
+        let (mut top, mut bottom) = (min.y, max.y);
+
+        if tile.flip_x {
+            std::mem::swap(&mut left, &mut right);
+        }
+
+        if tile.flip_y {
+            std::mem::swap(&mut top, &mut bottom);
+        }
+
+        let mut uvs = [[left, bottom], [right, bottom], [right, top], [left, top]];
+
+        // Turning the texture counter-clockwise moves every
+        // corner's coordinates to the next corner:
+        uvs.rotate_right(tile.turns as usize % 4);
+        uvs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bevy::render::mesh::VertexAttributeValues;
+
+    use crate::{ChunkData, ChunkSize};
+
+    fn uvs(mesh: &Mesh) -> Vec<[f32; 2]> {
+        match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+            Some(VertexAttributeValues::Float2(uvs)) => uvs.clone(),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn tiles_and_uvs() {
+        let mesher = TilemapMesher::grid(Vec2::new(16.0, 16.0), 2, 2);
+        let mut data = ChunkData::new_with(ChunkSize::new(4, 4), None);
+        data.set((0, 0), Some(AtlasIndex::new(3)));
+        data.set((1, 0), Some(AtlasIndex::new(1).flip_x()));
+        data.set((1, 1), Some(AtlasIndex::new(2).rotate(1)));
+        data.set((-1, 0), Some(AtlasIndex::new(4)));
+
+        let mesh = mesher.mesh(&data, CellSize::new(8, 8), |&tile| tile);
+        assert_eq!(12, mesh.count_vertices());
+
+        let uvs = uvs(&mesh);
+        // The bottom right tile of the atlas, numbered row by row from the top left:
+        assert_eq!([[0.5, 1.0], [1.0, 1.0], [1.0, 0.5], [0.5, 0.5]], uvs[4..8]);
+        // The top right tile of the atlas, flipped:
+        assert_eq!([[1.0, 0.5], [0.5, 0.5], [0.5, 0.0], [1.0, 0.0]], uvs[8..12]);
+        // The bottom left tile of the atlas turned a quarter counter-clockwise:
+        assert_eq!([[0.0, 0.5], [0.0, 1.0], [0.5, 1.0], [0.5, 0.5]], uvs[0..4]);
+    }
+
+    #[test]
+    fn neighbour_masks() {
+        let size = ChunkSize::new(4, 4);
+        let data = ChunkData::new_with_seed(size, |pos| pos.y >= 0);
+        let right = ChunkData::new_with_seed(size, |pos| pos.y >= 0);
+
+        let mut view = ChunkView::new(&data);
+        view.set_neighbour((1, 0), Some(&right));
+
+        let mesher = TilemapMesher::grid(Vec2::new(1.0, 1.0), 16, 16);
+        let masks = std::cell::RefCell::new(Vec::new());
+        mesher.autotile_mesh(
+            view,
+            CellSize::new(1, 1),
+            |&a, &b| a == b,
+            |&solid, mask| {
+                if solid {
+                    masks.borrow_mut().push(mask);
+                }
+                Some(AtlasIndex::new(mask.0 as usize))
+            },
+        );
+
+        // The top right tile connects to the right chunk, but not above it:
+        let top_right = masks.borrow()[3];
+        assert_eq!(
+            NeighbourMask::EAST
+                | NeighbourMask::SOUTH
+                | NeighbourMask::SOUTH_WEST
+                | NeighbourMask::WEST
+                | NeighbourMask::SOUTH_EAST,
+            top_right.0
+        );
+        assert_eq!(0b1110, top_right.sides());
+
+        let corner = NeighbourMask(NeighbourMask::NORTH | NeighbourMask::NORTH_EAST);
+        assert_eq!(NeighbourMask(NeighbourMask::NORTH), corner.blob());
+    }
+}
@@ -0,0 +1,59 @@
+use bevy::prelude::*;
+
+use crate::{CellPosition, ChunkGrid, ChunkPosition, FloatingOrigin};
+
+/// Keeps the `ChunkPosition` of an entity up to date from its
+/// `GlobalTransform`. A `ChunkPosition` is added if it is missing.
+///
+/// If the entity also has a `CellPosition` it is kept up to date too.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ChunkTracked;
+
+/// Give tracked entities their first `ChunkPosition`.
+pub fn insert_chunk_positions(
+    commands: &mut Commands,
+    grid: Res<ChunkGrid>,
+    origin: Res<FloatingOrigin>,
+    untracked: Query<(Entity, &GlobalTransform), (With<ChunkTracked>, Without<ChunkPosition>)>,
+) {
+    for (entity, transform) in untracked.iter() {
+        let local = transform.translation.truncate();
+        let (position, _) = origin.to_absolute(grid.chunk_size, grid.cell_size, local);
+
+        commands.insert_one(entity, position);
+    }
+}
+
+/// Update the positions of tracked entities. Positions are only written
+/// when they differ, so `Changed<ChunkPosition>` only fires when an
+/// entity moves into another chunk.
+pub fn track_chunk_positions(
+    grid: Res<ChunkGrid>,
+    origin: Res<FloatingOrigin>,
+    mut tracked: Query<
+        (
+            &GlobalTransform,
+            &mut ChunkPosition,
+            Option<&mut CellPosition>,
+        ),
+        With<ChunkTracked>,
+    >,
+) {
+    for (transform, mut chunk, cell) in tracked.iter_mut() {
+        let local = transform.translation.truncate();
+
+        let (current, _) = origin.to_absolute(grid.chunk_size, grid.cell_size, local);
+        if *chunk != current {
+            *chunk = current;
+        }
+
+        if let Some(mut cell) = cell {
+            // The origin is always on a chunk, so cells line
+            // up the same in the local and absolute frames:
+            let current = CellPosition::from_world(grid.chunk_size, grid.cell_size, local);
+            if *cell != current {
+                *cell = current;
+            }
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+
+use crate::{CellPosition, CellRect, ChunkData, ChunkPosition, ChunkSize};
+
+/// The cells of a chunk along with the cells of the eight neighbours
+/// around it.
+///
+/// Meshers sample the squares between cells, and a chunk owns the squares
+/// whose top left corner is one of its cells. The squares along its right
+/// and bottom edges need the cells of its neighbours, so the meshes of
+/// neighbouring chunks meet without a gap. Tilemaps look at the cells all
+/// around a tile to pick it. Missing neighbours are not loaded yet, the
+/// squares and tiles that need them are left out.
+pub struct ChunkView<'a, T> {
+    /// The position of the chunk, for meshes lining up with the world.
+    pub position: ChunkPosition,
+    pub data: &'a ChunkData<T>,
+    /// The neighbours in the same order as `NEIGHBOURS`.
+    pub neighbours: [Option<&'a ChunkData<T>>; 8],
+    /// Cells that replace the ones of the chunk and its neighbours, by
+    /// their position in the view, like the blended borders of `LodCells`.
+    pub overlay: Option<&'a HashMap<CellPosition, T>>,
+}
+
+impl<'a, T> ChunkView<'a, T> {
+    /// The offsets of the neighbours a chunk's mesh can sample,
+    /// from the top left to the bottom right.
+    pub const NEIGHBOURS: [(i32, i32); 8] = [
+        (-1, 1),
+        (0, 1),
+        (1, 1),
+        (-1, 0),
+        (1, 0),
+        (-1, -1),
+        (0, -1),
+        (1, -1),
+    ];
+
+    /// The neighbours the squares of `MarchingSquares` sample:
+    /// right, below and below right.
+    pub const SQUARES: [(i32, i32); 3] = [(1, 0), (0, -1), (1, -1)];
+
+    /// A view of a chunk without any of its neighbours.
+    pub fn new(data: &'a ChunkData<T>) -> Self {
+        Self {
+            position: ChunkPosition::new(0, 0),
+            data,
+            neighbours: [None; 8],
+            overlay: None,
+        }
+    }
+
+    /// A view of a chunk with the neighbours returned by `neighbour`,
+    /// given the offset of each neighbour from the chunk.
+    pub fn with_neighbours<F>(data: &'a ChunkData<T>, mut neighbour: F) -> Self
+    where
+        F: FnMut(ChunkPosition) -> Option<&'a ChunkData<T>>,
+    {
+        let mut view = Self::new(data);
+
+        for (i, &offset) in Self::NEIGHBOURS.iter().enumerate() {
+            view.neighbours[i] = neighbour(offset.into());
+        }
+
+        view
+    }
+
+    /// The same view, of the chunk at `position`.
+    pub fn at(mut self, position: ChunkPosition) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// The neighbour at `offset` from the chunk.
+    pub fn neighbour(&self, offset: (i32, i32)) -> Option<&'a ChunkData<T>> {
+        let i = Self::NEIGHBOURS.iter().position(|&o| o == offset)?;
+        self.neighbours[i]
+    }
+
+    /// Set the neighbour at `offset` from the chunk.
+    pub fn set_neighbour(&mut self, offset: (i32, i32), data: Option<&'a ChunkData<T>>) {
+        if let Some(i) = Self::NEIGHBOURS.iter().position(|&o| o == offset) {
+            self.neighbours[i] = data;
+        }
+    }
+
+    pub fn size(&self) -> ChunkSize {
+        self.data.size
+    }
+
+    /// The cell at `pos`, which can be one column or row
+    /// past any side of the chunk.
+    pub fn get(&self, pos: impl Into<CellPosition>) -> Option<&'a T> {
+        let mut pos = pos.into();
+
+        if let Some(cell) = self.overlay.and_then(|overlay| overlay.get(&pos)) {
+            return Some(cell);
+        }
+
+        let size = self.data.size;
+        let (top_left, bottom_right) = (size.top_left(), size.bottom_right());
+        let (width, height) = (size.width as i32, size.height as i32);
+
+        let dx = if pos.x == top_left.x - 1 {
+            -1
+        } else if pos.x == bottom_right.x + 1 {
+            1
+        } else {
+            0
+        };
+
+        let dy = if pos.y == top_left.y + 1 {
+            1
+        } else if pos.y == bottom_right.y - 1 {
+            -1
+        } else {
+            0
+        };
+
+        pos.x -= dx * width;
+        pos.y -= dy * height;
+
+        let data = match (dx, dy) {
+            (0, 0) => Some(self.data),
+            offset => self.neighbour(offset),
+        };
+
+        data?.get(pos)
+    }
+}
+
+impl<'a, T> Clone for ChunkView<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for ChunkView<'a, T> {}
+
+impl<'a, T> From<&'a ChunkData<T>> for ChunkView<'a, T> {
+    fn from(data: &'a ChunkData<T>) -> Self {
+        Self::new(data)
+    }
+}
+
+/// The chunks whose meshes sample the cell at `pos`, besides its own
+/// chunk, for meshers that sample the neighbours at `sampled`, like
+/// `ChunkView::SQUARES`. Returns the offset of each chunk along with the
+/// cells around `pos` in that chunk, so they can be marked dirty.
+pub fn dependent_chunks(
+    size: ChunkSize,
+    pos: CellPosition,
+    sampled: &[(i32, i32)],
+) -> Vec<(ChunkPosition, CellRect)> {
+    let (top_left, bottom_right) = (size.top_left(), size.bottom_right());
+    let bounds = CellRect::new(top_left, bottom_right);
+    let (width, height) = (size.width as i32, size.height as i32);
+
+    let on_side = |offset: i32, first: bool, last: bool| match offset {
+        -1 => first,
+        1 => last,
+        _ => true,
+    };
+
+    // A chunk looks one cell past its sides, so the cell is
+    // seen by the chunks across the sides it is on:
+    sampled
+        .iter()
+        .map(|&(dx, dy)| (-dx, -dy))
+        .filter(|&(dx, dy)| {
+            on_side(dx, pos.x == top_left.x, pos.x == bottom_right.x)
+                && on_side(dy, pos.y == bottom_right.y, pos.y == top_left.y)
+        })
+        .filter_map(|(dx, dy)| {
+            let seen = CellPosition::new(pos.x - dx * width, pos.y - dy * height);
+            let around = CellRect::new(
+                CellPosition::new(seen.x - 1, seen.y - 1),
+                CellPosition::new(seen.x + 1, seen.y + 1),
+            );
+
+            Some((ChunkPosition::new(dx, dy), around.intersection(bounds)?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbour_cells() {
+        let size = ChunkSize::new(4, 4);
+        let data = ChunkData::new_with(size, 0);
+        let right = ChunkData::new_with_seed(size, |pos| pos.y);
+        let below = ChunkData::new_with_seed(size, |pos| pos.x);
+        let above_left = ChunkData::new_with(size, 7);
+
+        let mut view = ChunkView::new(&data);
+        view.set_neighbour((1, 0), Some(&right));
+        view.set_neighbour((0, -1), Some(&below));
+        view.set_neighbour((-1, 1), Some(&above_left));
+
+        assert_eq!(Some(&0), view.get((1, 2)));
+        assert_eq!(Some(&-1), view.get((2, -1)));
+        assert_eq!(Some(&2), view.get((2, 2)));
+        assert_eq!(Some(&-2), view.get((-2, -2)));
+        assert_eq!(Some(&7), view.get((-3, 3)));
+        assert_eq!(None, view.get((2, -2)));
+        assert_eq!(None, view.get((-3, 0)));
+        assert_eq!(None, view.get((3, 0)));
+        assert_eq!(None, view.get((0, -3)));
+    }
+
+    #[test]
+    fn border_dependents() {
+        let size = ChunkSize::new(4, 4);
+        let squares = &ChunkView::<()>::SQUARES;
+        let all = &ChunkView::<()>::NEIGHBOURS;
+
+        assert!(dependent_chunks(size, (0, 0).into(), all).is_empty());
+        assert!(dependent_chunks(size, (0, 1).into(), all).is_empty());
+
+        // The top left corner is seen from the left, top and top left:
+        let corner = dependent_chunks(size, size.top_left(), squares);
+        assert_eq!(
+            vec![
+                (
+                    ChunkPosition::new(-1, 0),
+                    CellRect::new((1, 1).into(), (1, 2).into())
+                ),
+                (
+                    ChunkPosition::new(0, 1),
+                    CellRect::new((-2, -1).into(), (-1, -1).into())
+                ),
+                (ChunkPosition::new(-1, 1), CellRect::cell((1, -1).into())),
+            ],
+            {
+                let mut corner = corner;
+                corner.sort_by_key(|&(offset, _)| (offset.y, -offset.x));
+                corner
+            }
+        );
+
+        // Only the chunk below looks up at the bottom row:
+        assert!(dependent_chunks(size, (0, -1).into(), squares).is_empty());
+
+        let bottom = dependent_chunks(size, (0, -1).into(), all);
+        assert_eq!(
+            vec![(
+                ChunkPosition::new(0, -1),
+                CellRect::new((-1, 2).into(), (1, 2).into())
+            )],
+            bottom
+        );
+    }
+}